@@ -1,7 +1,10 @@
-use std::{error::Error, io, path::Path};
+use std::{cmp::Ordering, collections::BTreeMap, convert::TryFrom, error::Error, io, path::Path};
+use chrono::Timelike;
+use rand::{Rng, distributions::{Distribution, WeightedIndex}};
 use serde::{Serialize, Deserialize};
 
 pub const DEFAULT_CONFIG_PATH: &str = "./config.json";
+pub const DEFAULT_DOWNLOAD_PATH: &str = "download";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum DownloadQuality {
@@ -9,7 +12,369 @@ pub enum DownloadQuality {
     Full,
     Regular,
     Small,
-    Thumb
+    Thumb,
+    // Exactly as large as the display, where the source can resize photos, otherwise `Full`
+    FitToDisplay
+}
+
+// Image formats photos are saved in, by their magic bytes rather than the URL
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Bmp,
+    Webp,
+    Avif
+}
+
+// Overrides the detected display size for `FitToDisplay`, `dpr` being the scale factor
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "default_dpr")]
+    pub dpr: f32
+}
+
+fn default_dpr() -> f32 {
+    1.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum FitMode {
+    #[default]
+    Fill,
+    Fit,
+    Center,
+    Tile,
+    Stretch,
+    Span
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum WallpaperTarget {
+    #[default]
+    Desktop,
+    LockScreen,
+    Both
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+    Squarish
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutputConfig {
+    pub name: String,
+    pub query: Option<String>,
+    pub orientation: Option<Orientation>,
+    pub quality: Option<DownloadQuality>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UnsplashColor {
+    BlackAndWhite,
+    Black,
+    White,
+    Yellow,
+    Orange,
+    Red,
+    Purple,
+    Magenta,
+    Green,
+    Teal,
+    Blue
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ContentFilter {
+    Low,
+    High
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UnsplashOrder {
+    Relevant,
+    Latest
+}
+
+// How Unsplash photos are picked: among a page of search results, or by Unsplash itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum UnsplashMode {
+    #[default]
+    Search,
+    Random
+}
+
+// Passed along with every Unsplash search, anything left out is up to Unsplash. Without an
+// `orientation` the one of the display being changed is used
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct UnsplashFilters {
+    pub orientation: Option<Orientation>,
+    pub color: Option<UnsplashColor>,
+    pub content_filter: Option<ContentFilter>,
+    pub order_by: Option<UnsplashOrder>,
+    // ISO 639-1 code of the language the query is in, e.g. "de"
+    pub lang: Option<String>
+}
+
+// Photos picked by hand on Unsplash, by collection ID, topic slug or the username whose likes they are
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnsplashList {
+    Collection(String),
+    Topic(String),
+    Likes(String)
+}
+
+// e.g. { "buckets": ["night", "late night"], "collection": "1065976" }, without `buckets` it's used all day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnsplashCurated {
+    #[serde(default)]
+    pub buckets: Vec<String>,
+    #[serde(flatten)]
+    pub list: UnsplashList
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum RedditSort {
+    #[default]
+    Hot,
+    Top,
+    New
+}
+
+// How far back `Top` looks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum RedditTime {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+    Year,
+    All
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum SourceKind {
+    // Searches, unless one of `lists` is meant for the current time of day
+    Unsplash {
+        #[serde(default)]
+        lists: Vec<UnsplashCurated>
+    },
+    Pexels,
+    // `match_any` uses any photo when none of them match the query
+    Local {
+        dirs: Vec<String>,
+        #[serde(default)]
+        match_any: bool
+    },
+    // `market` picks the regional image, e.g. "en-GB"
+    Bing {
+        market: Option<String>
+    },
+    Wikimedia,
+    // Uses NASA's `DEMO_KEY` unless `api_key` is set
+    Apod {
+        api_key: Option<String>
+    },
+    // e.g. { "type": "Reddit", "subreddit": "EarthPorn", "sort": "Top", "time": "Week", "min_width": 1920 }
+    Reddit {
+        subreddit: String,
+        #[serde(default)]
+        sort: RedditSort,
+        #[serde(default)]
+        time: RedditTime,
+        #[serde(default)]
+        min_width: u32,
+        #[serde(default)]
+        min_height: u32
+    },
+    // An RSS 2.0 or Atom feed with image enclosures or Media RSS
+    Feed {
+        url: String
+    },
+    // Any JSON API, e.g. { "type": "Json", "url": "https://example.com/api?q={query}",
+    // "results": "data.items", "id": "id", "image": "urls.full", "attribution": "user.name" }
+    Json {
+        name: Option<String>,
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        results: String,
+        id: String,
+        image: String,
+        attribution: Option<String>
+    }
+}
+
+// e.g. { "type": "Unsplash", "weight": 2 }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceConfig {
+    #[serde(flatten)]
+    pub kind: SourceKind,
+    #[serde(default = "default_weight")]
+    pub weight: u32
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_download_dir() -> String {
+    String::from(DEFAULT_DOWNLOAD_PATH)
+}
+
+fn default_max_pages() -> usize {
+    10
+}
+
+// An hour of the day, 0 to 23
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct Hour(u32);
+
+impl TryFrom<u32> for Hour {
+    type Error = String;
+
+    fn try_from(hour: u32) -> Result<Hour, String> {
+        if hour < 24 {
+            Ok(Hour(hour))
+        } else {
+            Err(format!("{} is not an hour of the day", hour))
+        }
+    }
+}
+
+impl From<Hour> for u32 {
+    fn from(hour: Hour) -> u32 {
+        hour.0
+    }
+}
+
+impl Hour {
+    pub fn of(time: &impl Timelike) -> Hour {
+        Hour::try_from(time.hour()).expect("Hours go up to 23")
+    }
+}
+
+// A term searched for during a bucket, either "dusk" or { "term": "dusk", "weight": 3 }
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BucketTerm {
+    Plain(String),
+    Weighted {
+        term: String,
+        weight: u32
+    }
+}
+
+impl BucketTerm {
+    pub fn term(&self) -> &str {
+        match self {
+            BucketTerm::Plain(term) => term,
+            BucketTerm::Weighted { term, .. } => term
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            BucketTerm::Plain(_) => 1,
+            BucketTerm::Weighted { weight, .. } => *weight
+        }
+    }
+}
+
+// The hours from `from` up to `to`, wrapping past midnight when `to` comes first, and the whole day
+// when they're the same. One of `terms` goes into the query, or `name` without any. `name` is also
+// what Unsplash `lists` refer to in their `buckets`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimeBucket {
+    pub name: String,
+    pub from: Hour,
+    pub to: Hour,
+    #[serde(default)]
+    pub terms: Vec<BucketTerm>
+}
+
+impl TimeBucket {
+    pub fn contains(&self, hour: Hour) -> bool {
+        let (from, to, hour) = (u32::from(self.from), u32::from(self.to), u32::from(hour));
+        match from.cmp(&to) {
+            Ordering::Equal => true,
+            Ordering::Less => from <= hour && hour < to,
+            Ordering::Greater => hour >= from || hour < to
+        }
+    }
+
+    // One of the terms at random by weight, or the name when there are none to pick from. Blank ones
+    // are left out, so a bucket with neither adds nothing to the query
+    pub fn pick_term(&self, rng: &mut impl Rng) -> Option<String> {
+        let weights: Vec<u32> = self.terms.iter()
+            .map(|term| if term.term().trim().is_empty() { 0 } else { term.weight() })
+            .collect();
+        match WeightedIndex::new(&weights) {
+            Ok(index) => Some(String::from(self.terms[index.sample(rng)].term().trim())),
+            Err(_) => Some(String::from(self.name.trim())).filter(|name| !name.is_empty())
+        }
+    }
+}
+
+// The first bucket covering `hour`
+pub fn time_bucket(buckets: &[TimeBucket], hour: Hour) -> Option<&TimeBucket> {
+    buckets.iter().find(|bucket| bucket.contains(hour))
+}
+
+fn default_time_buckets() -> Vec<TimeBucket> {
+    [
+        ("midnight", 1, 4),
+        ("twilight", 4, 6),
+        ("sunrise", 6, 8),
+        ("morning", 8, 10),
+        ("day", 10, 12),
+        ("noon", 12, 14),
+        ("afternoon", 14, 17),
+        ("sunset", 17, 19),
+        ("evening", 19, 21),
+        ("night", 21, 23),
+        ("late night", 23, 1)
+    ].iter().map(|&(name, from, to)| TimeBucket {
+        name: String::from(name),
+        from: Hour(from),
+        to: Hour(to),
+        terms: Vec::new()
+    }).collect()
+}
+
+fn default_sources() -> Vec<SourceConfig> {
+    vec![SourceConfig {
+        kind: SourceKind::Unsplash {
+            lists: Vec::new()
+        },
+        weight: 1
+    }]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Desktop {
+    Gnome,
+    Kde,
+    Xfce,
+    Mate,
+    Cinnamon,
+    Feh,
+    Nitrogen,
+    Xwallpaper,
+    Sway,
+    Swaybg,
+    Swww,
+    Hyprpaper
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -17,10 +382,52 @@ pub struct Config {
     pub repeat_secs: u64,
     pub update_interval: u64,
     pub quality: DownloadQuality,
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    // Asked of sources that can convert, e.g. Unsplash, when the desktop can show it
+    #[serde(default)]
+    pub image_format: Option<ImageFormat>,
+    #[serde(default)]
+    pub fit: FitMode,
+    #[serde(default)]
+    pub target: WallpaperTarget,
+    #[serde(default)]
+    pub distinct_lock_screen: bool,
+    #[serde(default = "default_sources")]
+    pub sources: Vec<SourceConfig>,
     pub unsplash_access_key: Option<String>,
+    // Where the APIs are, for proxies and tests. They can also be set through `AWC_UNSPLASH_API_URL`
+    // and `AWC_OPENWEATHER_API_URL`
+    #[serde(default)]
+    pub unsplash_api_url: Option<String>,
+    #[serde(default)]
+    pub unsplash_filters: UnsplashFilters,
+    // Random pages are picked among the first `unsplash_max_pages` of the results
+    #[serde(default = "default_max_pages")]
+    pub unsplash_max_pages: usize,
+    #[serde(default)]
+    pub unsplash_mode: UnsplashMode,
+    // Maps the hour to the time of day part of the query
+    #[serde(default = "default_time_buckets")]
+    pub time_buckets: Vec<TimeBucket>,
+    pub pexels_access_key: Option<String>,
     pub openweather_access_key: Option<String>,
+    #[serde(default)]
+    pub openweather_api_url: Option<String>,
     pub city_weather: String,
-    pub disable_cache: bool
+    pub disable_cache: bool,
+    // Where photos are saved, relative to the working directory unless absolute
+    #[serde(default = "default_download_dir")]
+    pub download_dir: String,
+    pub desktop: Option<Desktop>,
+    pub setter_command: Option<Vec<String>>,
+    pub export_dir: Option<String>,
+    #[serde(default)]
+    pub export_only: bool,
+    #[serde(default)]
+    pub per_output: bool,
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>
 }
 
 impl Config {
@@ -33,10 +440,30 @@ impl Config {
             repeat_secs: 1,
             update_interval: 3600,
             quality: DownloadQuality::Full,
+            resolution: None,
+            image_format: None,
+            fit: FitMode::Fill,
+            target: WallpaperTarget::Desktop,
+            distinct_lock_screen: false,
+            sources: default_sources(),
             unsplash_access_key: None,
+            unsplash_api_url: None,
+            unsplash_filters: UnsplashFilters::default(),
+            unsplash_max_pages: default_max_pages(),
+            unsplash_mode: UnsplashMode::Search,
+            time_buckets: default_time_buckets(),
+            pexels_access_key: None,
             openweather_access_key: None,
+            openweather_api_url: None,
             city_weather: String::from("Dublin"),
-            disable_cache: true
+            disable_cache: true,
+            download_dir: default_download_dir(),
+            desktop: None,
+            setter_command: None,
+            export_dir: None,
+            export_only: false,
+            per_output: false,
+            outputs: Vec::new()
         };
         let config_str = serde_json::to_string(&config)?;
         std::fs::write(path, config_str)?;
@@ -49,3 +476,58 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    #[test]
+    fn test_time_buckets() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "repeat_secs": 1,
+            "update_interval": 3600,
+            "quality": "Full",
+            "unsplash_access_key": null,
+            "pexels_access_key": null,
+            "openweather_access_key": null,
+            "city_weather": "Dublin",
+            "disable_cache": true,
+            "desktop": null,
+            "setter_command": null,
+            "export_dir": null
+        })).unwrap();
+        let names: Vec<&str> = (0..24)
+            .map(|hour| time_bucket(&config.time_buckets, Hour::try_from(hour).unwrap()).unwrap().name.as_str())
+            .collect();
+        assert_eq!(names, vec!["late night", "midnight", "midnight", "midnight", "twilight", "twilight", "sunrise", "sunrise",
+            "morning", "morning", "day", "day", "noon", "noon", "afternoon", "afternoon", "afternoon", "sunset", "sunset",
+            "evening", "evening", "night", "night", "late night"]);
+        assert_eq!(Hour::of(&Local::now().time()), Hour::try_from(Local::now().hour()).unwrap());
+
+        let buckets: Vec<TimeBucket> = serde_json::from_str(r#"[
+            {"name": "golden hour", "from": 18, "to": 20, "terms": ["golden hour", {"term": "dusk", "weight": 3}, {"term": "never", "weight": 0}]},
+            {"name": "dark", "from": 22, "to": 5}
+        ]"#).unwrap();
+        assert_eq!(time_bucket(&buckets, Hour::try_from(2).unwrap()).unwrap().name, "dark");
+        assert_eq!(time_bucket(&buckets, Hour::try_from(20).unwrap()), None);
+        assert_eq!(buckets[1].pick_term(&mut rand::thread_rng()).as_deref(), Some("dark"));
+        let mut rng = StdRng::seed_from_u64(7);
+        let dusks = (0..1000).filter(|_| buckets[0].pick_term(&mut rng).as_deref() == Some("dusk")).count();
+        assert!(dusks > 650 && dusks < 850, "{} out of 1000", dusks);
+        assert!((0..100).all(|_| buckets[0].pick_term(&mut rng).as_deref() != Some("never")));
+
+        // Nothing to search for, rather than an empty term
+        let blank: Vec<TimeBucket> = serde_json::from_str(r#"[
+            {"name": "", "from": 0, "to": 0},
+            {"name": " ", "from": 0, "to": 0, "terms": ["", {"term": " ", "weight": 2}]},
+            {"name": "dawn", "from": 0, "to": 0, "terms": [""]}
+        ]"#).unwrap();
+        assert_eq!(blank[0].pick_term(&mut rng), None);
+        assert_eq!(blank[1].pick_term(&mut rng), None);
+        assert_eq!(blank[2].pick_term(&mut rng).as_deref(), Some("dawn"));
+        assert!(serde_json::from_str::<TimeBucket>(r#"{"name": "nope", "from": 24, "to": 1}"#).is_err());
+        assert_eq!(serde_json::to_string(&buckets[1]).unwrap(), r#"{"name":"dark","from":22,"to":5,"terms":[]}"#);
+    }
+}
@@ -0,0 +1,318 @@
+use std::{error::Error, fmt, fs::{self, File}, io::{self, ErrorKind, Read}, path::Path, process::{Command, Stdio}};
+
+use image::{GenericImageView, io::Reader};
+
+use crate::config::ImageFormat;
+
+const ALL_FORMATS: &[ImageFormat] = &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif, ImageFormat::Bmp, ImageFormat::Webp, ImageFormat::Avif];
+
+// Anything smaller is an error page or a placeholder rather than a photo
+const MIN_FILE_SIZE: u64 = 1024;
+const MIN_SIDE: u32 = 64;
+const QUARANTINE_DIR: &str = "quarantine";
+
+// Programs that can turn one image format into another, tried in order
+const CONVERTERS: &[&[&str]] = &[
+    &["magick", "{in}", "{out}"],
+    &["convert", "{in}", "{out}"],
+    &["vips", "copy", "{in}", "{out}"],
+    &["ffmpeg", "-loglevel", "error", "-y", "-i", "{in}", "{out}"],
+    &["sips", "-s", "format", "{format}", "{in}", "--out", "{out}"]
+];
+
+impl ImageFormat {
+    pub fn all() -> &'static [ImageFormat] {
+        ALL_FORMATS
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif"
+        }
+    }
+
+    // What `sips` calls it
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpeg",
+            _ => self.extension()
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "jpeg" | "jpe" => Some(ImageFormat::Jpeg),
+            _ => ALL_FORMATS.iter().find(|format| format.extension() == extension).copied()
+        }
+    }
+
+    pub fn from_magic(data: &[u8]) -> Option<ImageFormat> {
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(ImageFormat::Gif)
+        } else if data.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(ImageFormat::Webp)
+        } else if data.len() >= 12 && &data[4..8] == b"ftyp" && (&data[8..12] == b"avif" || &data[8..12] == b"avis") {
+            Some(ImageFormat::Avif)
+        } else {
+            None
+        }
+    }
+
+    // e.g. "image/jpeg; charset=binary"
+    pub fn from_content_type(content_type: &str) -> Option<ImageFormat> {
+        let mime = content_type.split(';').next()?.trim().to_lowercase();
+        match mime.as_str() {
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/bmp" | "image/x-ms-bmp" => Some(ImageFormat::Bmp),
+            "image/webp" => Some(ImageFormat::Webp),
+            "image/avif" => Some(ImageFormat::Avif),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidImage {
+    pub path: String,
+    pub reason: String
+}
+
+impl fmt::Display for InvalidImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not a usable image: {}", self.path, self.reason)
+    }
+}
+
+impl Error for InvalidImage {}
+
+// Decodes the whole file, so truncated downloads and error pages never reach the desktop
+pub fn validate(path: &str) -> Result<(), InvalidImage> {
+    let invalid = |reason: String| InvalidImage {
+        path: String::from(path),
+        reason
+    };
+    let size = fs::metadata(path).map_err(|e| invalid(e.to_string()))?.len();
+    if size < MIN_FILE_SIZE {
+        return Err(invalid(format!("only {} bytes", size)));
+    }
+    let mut magic = Vec::new();
+    File::open(path).and_then(|file| file.take(16).read_to_end(&mut magic)).map_err(|e| invalid(e.to_string()))?;
+    match ImageFormat::from_magic(&magic) {
+        None => return Err(invalid(String::from("unknown format"))),
+        // There's no AVIF decoder to hand, and a header alone proves nothing. No setter shows AVIF
+        // either, so `store_download` converts it before it gets here
+        Some(ImageFormat::Avif) => return Err(invalid(String::from("AVIF can't be decoded to check it"))),
+        Some(_) => ()
+    }
+    let image = Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| invalid(e.to_string()))?
+        .decode()
+        .map_err(|e| invalid(e.to_string()))?;
+    let (width, height) = image.dimensions();
+    if width < MIN_SIDE || height < MIN_SIDE {
+        return Err(invalid(format!("only {}x{}", width, height)));
+    }
+    Ok(())
+}
+
+// Moves a bad file into `quarantine` next to it, out of the way of the cache but still there to look at
+pub(crate) fn quarantine(path: &str) -> Result<String, io::Error> {
+    let path = Path::new(path);
+    let dir = path.parent().unwrap_or_else(|| Path::new(".")).join(QUARANTINE_DIR);
+    fs::create_dir_all(&dir)?;
+    let target = dir.join(path.file_name().unwrap_or_default());
+    fs::rename(path, &target)?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+// Hands back `path` if it holds a usable image, otherwise quarantines it
+pub(crate) fn check_image(path: String) -> Result<String, Box<dyn Error>> {
+    if let Err(e) = validate(&path) {
+        match quarantine(&path) {
+            Ok(target) => eprintln!("{}. Moved it to {}...", e, target),
+            Err(quarantine_error) => eprintln!("{}. Failed to quarantine it: {}", e, quarantine_error)
+        }
+        return Err(Box::new(e));
+    }
+    Ok(path)
+}
+
+// A photo saved earlier as `{stem}.<any extension>`
+pub(crate) fn find_saved(stem: &str) -> Option<String> {
+    ALL_FORMATS.iter()
+        .map(|format| format!("{}.{}", stem, format.extension()))
+        .find(|path| Path::new(path).is_file())
+}
+
+// Moves a finished download at `part` to `{stem}.<extension>`, going by its magic bytes, then by
+// what the server said it sent. Formats outside `formats` are converted to the first of them, and
+// dropped when that fails since the desktop couldn't show them anyway
+pub(crate) fn store_download(part: &str, stem: &str, hint: Option<ImageFormat>, formats: &[ImageFormat]) -> Result<String, Box<dyn Error>> {
+    let mut magic = Vec::new();
+    File::open(part)?.take(16).read_to_end(&mut magic)?;
+    let format = ImageFormat::from_magic(&magic).or(hint).unwrap_or(ImageFormat::Jpeg);
+    let path = format!("{}.{}", stem, format.extension());
+    if let Some(target) = formats.first().filter(|_| !formats.contains(&format)) {
+        let converted = format!("{}.{}", stem, target.extension());
+        let conversion = convert(part, &converted, *target);
+        fs::remove_file(part)?;
+        return match conversion {
+            Ok(()) => Ok(converted),
+            Err(e) => {
+                let _ = fs::remove_file(&converted);
+                Err(format!("Failed to convert {} to {:?}: {}", path, target, e).into())
+            }
+        };
+    }
+    fs::rename(part, &path)?;
+    Ok(path)
+}
+
+// Converters work out the format from the file name, so `from` is copied somewhere that says what it is
+fn convert(from: &str, to: &str, format: ImageFormat) -> Result<(), io::Error> {
+    let mut magic = Vec::new();
+    File::open(from)?.take(16).read_to_end(&mut magic)?;
+    let source = match ImageFormat::from_magic(&magic) {
+        Some(source) => format!("{}.{}", from, source.extension()),
+        None => String::from(from)
+    };
+    if source != from {
+        fs::copy(from, &source)?;
+    }
+    let result = run_converters(&source, to, format);
+    if source != from {
+        let _ = fs::remove_file(&source);
+    }
+    result
+}
+
+fn run_converters(from: &str, to: &str, format: ImageFormat) -> Result<(), io::Error> {
+    for template in CONVERTERS {
+        // Windows has a `convert` of its own, which converts file systems
+        if cfg!(windows) && template[0] == "convert" {
+            continue;
+        }
+        let args: Vec<String> = template[1..].iter()
+            .map(|arg| arg.replace("{in}", from).replace("{out}", to).replace("{format}", format.name()))
+            .collect();
+        let status = Command::new(template[0])
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if let Ok(status) = status {
+            if status.success() && Path::new(to).is_file() {
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::new(ErrorKind::NotFound, "no converter worked, install ImageMagick, libvips or ffmpeg"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn test_from_magic() {
+        assert_eq!(ImageFormat::from_magic(include_bytes!("../ferris.png")), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_magic(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F']), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_magic(b"RIFF\x24\x00\x00\x00WEBPVP8 "), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::from_magic(b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00"), Some(ImageFormat::Avif));
+        assert_eq!(ImageFormat::from_magic(b"GIF89a\x01\x00"), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::from_magic(b"<!DOCTYPE html>"), None);
+        assert_eq!(ImageFormat::from_magic(b""), None);
+    }
+
+    #[test]
+    fn test_from_content_type() {
+        assert_eq!(ImageFormat::from_content_type("image/webp"), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::from_content_type("Image/JPEG; charset=binary"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_content_type("text/html"), None);
+        assert_eq!(ImageFormat::from_extension(Path::new("a/b.JPEG")), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_extension(Path::new("a/b.part")), None);
+    }
+
+    #[test]
+    fn test_store_download() {
+        let dir = env::temp_dir().join(format!("awc-format-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let stem = dir.join("ferris").to_string_lossy().into_owned();
+        let part = format!("{}.part", stem);
+
+        // The magic bytes win over what the server claimed
+        fs::write(&part, include_bytes!("../ferris.png")).unwrap();
+        let path = store_download(&part, &stem, Some(ImageFormat::Jpeg), &[ImageFormat::Jpeg, ImageFormat::Png]).unwrap();
+        assert_eq!(path, format!("{}.png", stem));
+        assert!(!Path::new(&part).exists());
+        assert_eq!(find_saved(&stem), Some(path));
+
+        // Without recognisable magic bytes the hint is all there is
+        fs::write(&part, b"\x00\x00\x00\x1cftypheic").unwrap();
+        let path = store_download(&part, &stem, Some(ImageFormat::Webp), &[ImageFormat::Webp]).unwrap();
+        assert_eq!(path, format!("{}.webp", stem));
+
+        // Nothing can convert this, so it isn't kept
+        fs::write(&part, b"\x00\x00\x00\x1cftypheic").unwrap();
+        assert!(store_download(&part, &stem, Some(ImageFormat::Avif), &[ImageFormat::Jpeg]).is_err());
+        assert!(!Path::new(&part).exists());
+        assert!(!Path::new(&format!("{}.avif", stem)).exists());
+        assert!(!Path::new(&format!("{}.jpg", stem)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_image() {
+        let dir = env::temp_dir().join(format!("awc-check-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let ferris = include_bytes!("../ferris.png");
+
+        fs::write(path("ferris.png"), ferris).unwrap();
+        assert_eq!(check_image(path("ferris.png")).unwrap(), path("ferris.png"));
+
+        let mut jpeg = Vec::new();
+        image::load_from_memory(ferris).unwrap().write_to(&mut io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(80)).unwrap();
+        fs::write(path("ferris.jpg"), &jpeg).unwrap();
+        assert!(validate(&path("ferris.jpg")).is_ok());
+
+        // Cut off half way, an error page saved as a photo, a favicon, and an AVIF header with nothing
+        // that could be checked behind it
+        fs::write(path("truncated.png"), &ferris[..ferris.len() / 2]).unwrap();
+        fs::write(path("truncated.jpg"), &jpeg[..jpeg.len() * 2 / 3]).unwrap();
+        fs::write(path("error.jpg"), "<!DOCTYPE html><html><body>Service Unavailable</body></html>".repeat(20)).unwrap();
+        let mut icon = Vec::new();
+        image::DynamicImage::new_rgb8(16, 16).write_to(&mut io::Cursor::new(&mut icon), image::ImageOutputFormat::Png).unwrap();
+        icon.resize(2048, 0);
+        fs::write(path("icon.png"), &icon).unwrap();
+        let mut avif = b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00".to_vec();
+        avif.resize(2048, 0);
+        fs::write(path("header.avif"), &avif).unwrap();
+        for name in &["truncated.png", "truncated.jpg", "error.jpg", "icon.png", "header.avif"] {
+            let e = check_image(path(name)).unwrap_err();
+            assert!(e.is::<InvalidImage>(), "{}", e);
+            assert!(!Path::new(&path(name)).exists());
+            assert!(dir.join(QUARANTINE_DIR).join(name).exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,24 +2,101 @@ mod weather;
 mod config;
 mod wallpaper;
 mod worker;
+mod source;
+mod format;
 
-use std::{env, error::Error, fs::create_dir, io::{ErrorKind, Read}, path::Path};
+use std::{env, error::Error, fmt, io::Read, sync::Mutex, time::{Duration, SystemTime}};
 
-use reqwest::{blocking::Client, header::{HeaderMap, HeaderValue}};
+use reqwest::{StatusCode, blocking::{Client, Response}, header::{CONTENT_TYPE, HeaderMap, HeaderValue}};
 use serde::{Serialize, Deserialize};
 pub use config::Config;
-pub use weather::get_weather;
+pub use weather::{get_weather, openweather_url};
+pub use format::{InvalidImage, validate};
 
 pub use worker::{Worker, Message, MetaMessage, State};
 
-pub use crate::config::{DownloadQuality, DEFAULT_CONFIG_PATH};
-pub use crate::wallpaper::set_wallpaper::set_wallpaper;
+pub use crate::config::{BucketTerm, Desktop, DownloadQuality, FitMode, Hour, ImageFormat, Orientation, OutputConfig, Resolution, TimeBucket, WallpaperTarget, DEFAULT_CONFIG_PATH, DEFAULT_DOWNLOAD_PATH, time_bucket};
+pub use crate::wallpaper::{WallpaperSetter, CommandSetter, ExportSetter, Output, PhotoInfo, WallpaperInfo, make_wallpaper_setter, list_outputs};
+pub use crate::config::{ContentFilter, RedditSort, RedditTime, SourceConfig, SourceKind, UnsplashColor, UnsplashCurated, UnsplashFilters, UnsplashList, UnsplashMode, UnsplashOrder};
+pub use crate::source::{ApodSource, BingSource, FeedSource, JsonFields, JsonSource, LocalSource, PexelsSource, RedditSource, WikimediaSource, Photo, PhotoSource, Query, UnsplashSource, WeightedSource, download_cached, make_photo_source, make_photo_sources};
 
 const API_BASE_URL: &str = "https://api.unsplash.com";
-pub const DEFAULT_DOWNLOAD_PATH: &str = "download";
+const PEXELS_API_BASE_URL: &str = "https://api.pexels.com/v1";
 const MAXIMUM_PER_PAGE: i32 = 100;
+// Collections, topics and likes come 30 at most to a page
+pub const MAXIMUM_LIST_PER_PAGE: usize = 30;
+const MAXIMUM_RANDOM_COUNT: usize = 30;
+const PEXELS_MAXIMUM_PER_PAGE: i32 = 80;
+// What imgix calls `q`, a JPEG quality between 0 and 100
+const IMGIX_QUALITY: u32 = 80;
 
-#[derive(Serialize, Deserialize, Debug)]
+// The hourly request budget of the Unsplash key, as of the latest response
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let header = |name: &str| -> Option<u32> {
+            headers.get(name)?.to_str().ok()?.trim().parse().ok()
+        };
+        Some(RateLimit {
+            limit: header("X-Ratelimit-Limit")?,
+            remaining: header("X-Ratelimit-Remaining")?
+        })
+    }
+}
+
+// Unsplash answers with a 403 once the hourly budget is spent
+#[derive(Debug)]
+pub struct RateLimitExceeded;
+
+impl fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsplash rate limit exceeded, try again within the hour")
+    }
+}
+
+impl Error for RateLimitExceeded {}
+
+// Budgets are reset every hour, so an older reading says nothing
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(3600);
+
+// Shared by every client, since the budget belongs to the key. Kept with when it was read
+static RATE_LIMIT: Mutex<Option<(RateLimit, SystemTime)>> = Mutex::new(None);
+
+pub fn unsplash_rate_limit() -> Option<RateLimit> {
+    let (rate_limit, read) = (*RATE_LIMIT.lock().unwrap())?;
+    match read.elapsed() {
+        Ok(age) if age >= RATE_LIMIT_WINDOW => None,
+        _ => Some(rate_limit)
+    }
+}
+
+pub(crate) fn record_rate_limit(rate_limit: RateLimit, read: SystemTime) {
+    *RATE_LIMIT.lock().unwrap() = Some((rate_limit, read));
+}
+
+// Every Unsplash API request goes through here to keep track of the budget
+fn unsplash_get(client: &Client, url: &str) -> Result<Response, Box<dyn Error>> {
+    let response = client.get(url).send()?;
+    let rate_limit = RateLimit::from_headers(response.headers());
+    if let Some(rate_limit) = rate_limit {
+        record_rate_limit(rate_limit, SystemTime::now());
+    }
+    if response.status() != StatusCode::FORBIDDEN {
+        return Ok(response);
+    }
+    let body = response.text().unwrap_or_default();
+    if rate_limit.is_some_and(|rate_limit| rate_limit.remaining == 0) || body.contains("Rate Limit Exceeded") {
+        return Err(Box::new(RateLimitExceeded));
+    }
+    Err(format!("Unsplash refused the request: {}", body.trim()).into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Urls {
     pub raw: String,
     pub full: String,
@@ -28,18 +105,160 @@ pub struct Urls {
     pub thumb: String
 }
 
+impl Urls {
+    pub fn get(&self, quality: &DownloadQuality) -> &str {
+        match quality {
+            DownloadQuality::Raw => &self.raw,
+            DownloadQuality::Full => &self.full,
+            DownloadQuality::Regular => &self.regular,
+            DownloadQuality::Small => &self.small,
+            DownloadQuality::Thumb => &self.thumb,
+            DownloadQuality::FitToDisplay => &self.full
+        }
+    }
+
+    // Unsplash serves photos through imgix, which crops and scales them to order
+    pub fn fit(&self, resolution: &Resolution) -> Option<String> {
+        if !is_imgix(&self.raw) {
+            return None;
+        }
+        let separator = if self.raw.contains('?') { '&' } else { '?' };
+        Some(format!("{}{}w={}&h={}&fit=crop&dpr={}&q={}",
+            self.raw,
+            separator,
+            resolution.width,
+            resolution.height,
+            resolution.dpr,
+            IMGIX_QUALITY))
+    }
+}
+
+fn is_imgix(url: &str) -> bool {
+    let host = url.split("://").nth(1).and_then(|rest| rest.split('/').next());
+    matches!(host, Some("images.unsplash.com") | Some("plus.unsplash.com"))
+}
+
+// Asks imgix for another format, replacing the `fm=jpg` in Unsplash's sized URLs
+pub(crate) fn imgix_format(url: &str, format: ImageFormat) -> Option<String> {
+    if !is_imgix(url) || format == ImageFormat::Bmp {
+        return None;
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let fm = format!("fm={}", format.extension());
+    let params: Vec<&str> = query.split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("fm="))
+        .chain(std::iter::once(fm.as_str()))
+        .collect();
+    Some(format!("{}?{}", path, params.join("&")))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UserLinks {
+    pub html: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct User {
+    pub username: String,
+    pub name: String,
+    #[serde(default)]
+    pub links: UserLinks
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PhotoLinks {
+    pub html: String,
+    pub download: String,
+    // Has to be requested whenever the photo is used, see
+    // https://help.unsplash.com/en/articles/2511258-guideline-triggering-a-download
+    pub download_location: String
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SearchResult {
     pub id: String,
-    pub urls: Urls
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    pub color: Option<String>,
+    pub blur_hash: Option<String>,
+    pub description: Option<String>,
+    pub alt_description: Option<String>,
+    pub urls: Urls,
+    #[serde(default)]
+    pub links: PhotoLinks,
+    #[serde(default)]
+    pub user: User
+}
+
+impl SearchResult {
+    // The credit Unsplash asks for wherever the photo is shown
+    pub fn attribution(&self) -> String {
+        let name = if self.user.name.is_empty() {
+            &self.user.username
+        } else {
+            &self.user.name
+        };
+        format!("Photo by {} on Unsplash", name)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SearchResults {
     pub total: usize,
+    #[serde(default)]
+    pub total_pages: usize,
     pub results: Vec<SearchResult>
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PexelsSrc {
+    pub original: String,
+    pub large2x: String,
+    pub large: String,
+    pub medium: String,
+    pub small: String
+}
+
+// Pexels has more sizes than Unsplash, these are the closest to each `DownloadQuality`
+impl From<PexelsSrc> for Urls {
+    fn from(src: PexelsSrc) -> Urls {
+        Urls {
+            raw: src.original,
+            full: src.large2x,
+            regular: src.large,
+            small: src.medium,
+            thumb: src.small
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PexelsPhoto {
+    pub id: u64,
+    #[serde(default)]
+    pub photographer: String,
+    pub src: PexelsSrc
+}
+
+impl PexelsPhoto {
+    // Pexels asks for its photographers to be credited too
+    pub fn attribution(&self) -> Option<String> {
+        if self.photographer.is_empty() {
+            None
+        } else {
+            Some(format!("Photo by {} on Pexels", self.photographer))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PexelsResults {
+    pub total_results: usize,
+    pub photos: Vec<PexelsPhoto>
+}
+
 pub fn make_unsplash_client(config: &Config) -> Result<Client, Box<dyn Error>> {
     let mut default_headers = HeaderMap::new();
     default_headers.append("Authorization", HeaderValue::from_str(
@@ -55,119 +274,372 @@ pub fn make_unsplash_client(config: &Config) -> Result<Client, Box<dyn Error>> {
     Ok(client)
 }
 
-pub fn search_photos(client: &Client, query: &str) -> Result<SearchResults, Box<dyn Error>> {
-    let mut response = client.get(format!("{}/search/photos?query={}&per_page={}", 
-        API_BASE_URL,
+pub fn make_pexels_client(config: &Config) -> Result<Client, Box<dyn Error>> {
+    let mut default_headers = HeaderMap::new();
+    default_headers.append("Authorization", HeaderValue::from_str(
+        &match &config.pexels_access_key {
+            Some(key) => key.clone(),
+            None => {
+                env::var("AWC_PEXELS_KEY")?
+            }
+        })?);
+    let client = Client::builder()
+        .default_headers(default_headers)
+        .build()?;
+    Ok(client)
+}
+
+// `unsplash_api_url` or `AWC_UNSPLASH_API_URL`, e.g. to go through a proxy
+pub fn unsplash_api_url(config: &Config) -> String {
+    let url = match &config.unsplash_api_url {
+        Some(url) => url.clone(),
+        None => env::var("AWC_UNSPLASH_API_URL").unwrap_or_else(|_| String::from(API_BASE_URL))
+    };
+    String::from(url.trim_end_matches('/'))
+}
+
+fn orientation_param(orientation: &Orientation) -> &'static str {
+    match orientation {
+        Orientation::Landscape => "&orientation=landscape",
+        Orientation::Portrait => "&orientation=portrait",
+        Orientation::Squarish => "&orientation=squarish"
+    }
+}
+
+pub fn search_url(base_url: &str, query: &str, filters: &UnsplashFilters) -> String {
+    let mut url = format!("{}/search/photos?query={}&per_page={}", 
+        base_url,
         query,
-        MAXIMUM_PER_PAGE)).send()?;
+        MAXIMUM_PER_PAGE);
+    if let Some(orientation) = &filters.orientation {
+        url.push_str(orientation_param(orientation));
+    }
+    if let Some(color) = &filters.color {
+        url.push_str(match color {
+            UnsplashColor::BlackAndWhite => "&color=black_and_white",
+            UnsplashColor::Black => "&color=black",
+            UnsplashColor::White => "&color=white",
+            UnsplashColor::Yellow => "&color=yellow",
+            UnsplashColor::Orange => "&color=orange",
+            UnsplashColor::Red => "&color=red",
+            UnsplashColor::Purple => "&color=purple",
+            UnsplashColor::Magenta => "&color=magenta",
+            UnsplashColor::Green => "&color=green",
+            UnsplashColor::Teal => "&color=teal",
+            UnsplashColor::Blue => "&color=blue"
+        });
+    }
+    if let Some(content_filter) = &filters.content_filter {
+        url.push_str(match content_filter {
+            ContentFilter::Low => "&content_filter=low",
+            ContentFilter::High => "&content_filter=high"
+        });
+    }
+    if let Some(order_by) = &filters.order_by {
+        url.push_str(match order_by {
+            UnsplashOrder::Relevant => "&order_by=relevant",
+            UnsplashOrder::Latest => "&order_by=latest"
+        });
+    }
+    if let Some(lang) = &filters.lang {
+        url.push_str(&format!("&lang={}", lang));
+    }
+    url
+}
+
+pub fn search_photos(client: &Client, base_url: &str, query: &str, filters: &UnsplashFilters, page: usize) -> Result<SearchResults, Box<dyn Error>> {
+    let mut response = unsplash_get(client, &format!("{}&page={}", search_url(base_url, query, filters), page))?;
     let mut data = String::new();
     response.read_to_string(&mut data)?;
     let data: SearchResults = serde_json::from_str(&data)?;
     Ok(data)
 }
 
-pub fn download_photo(client: &Client, photo: &SearchResult, quality: DownloadQuality) -> Result<String, Box<dyn Error>> {
-    let save_path = format!("{}/{}.jpg", DEFAULT_DOWNLOAD_PATH, photo.id); 
-    if Path::new(&save_path).exists() {
-        return Ok(save_path);
+pub fn search_pexels(client: &Client, query: &str, orientation: Option<&Orientation>) -> Result<PexelsResults, Box<dyn Error>> {
+    let mut url = format!("{}/search?query={}&per_page={}",
+        PEXELS_API_BASE_URL,
+        query,
+        PEXELS_MAXIMUM_PER_PAGE);
+    if let Some(orientation) = orientation {
+        // Pexels' name for it is the only one that differs
+        url.push_str(match orientation {
+            Orientation::Squarish => "&orientation=square",
+            _ => orientation_param(orientation)
+        });
     }
-    let url = match quality {
-        DownloadQuality::Raw => &photo.urls.raw,
-        DownloadQuality::Full => &photo.urls.full,
-        DownloadQuality::Regular => &photo.urls.regular,
-        DownloadQuality::Small => &photo.urls.small,
-        DownloadQuality::Thumb => &photo.urls.thumb
-    };
     let mut response = client.get(url).send()?;
-    if let Err(e) = create_dir(DEFAULT_DOWNLOAD_PATH) {
-        if e.kind() != ErrorKind::AlreadyExists {
-            return Err(Box::new(e));
-        }
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    let data: PexelsResults = serde_json::from_str(&data)?;
+    Ok(data)
+}
+
+pub fn get_pexels_photo(client: &Client, id: &str) -> Result<PexelsPhoto, Box<dyn Error>> {
+    let mut response = client.get(format!("{}/photos/{}", PEXELS_API_BASE_URL, id)).send()?;
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    let data: PexelsPhoto = serde_json::from_str(&data)?;
+    Ok(data)
+}
+
+pub fn list_path(list: &UnsplashList) -> String {
+    match list {
+        UnsplashList::Collection(id) => format!("/collections/{}/photos", id),
+        UnsplashList::Topic(slug) => format!("/topics/{}/photos", slug),
+        UnsplashList::Likes(username) => format!("/users/{}/likes", username)
     }
-    let mut data = Vec::new();
-    response.read_to_end(&mut data)?;
+}
 
-    std::fs::write(&save_path, data)?;
-    Ok(save_path)
-}
-
-pub struct Hour(pub u32);
-
-impl ToString for Hour {
-    fn to_string(&self) -> String {
-        match self.0 {
-            1 | 2 | 3 => {
-                String::from("midnight")
-            },
-            4 | 5 => {
-                String::from("twilight")
-            },
-            6 | 7 => {
-                String::from("sunrise")
-            },
-            8 | 9 => {
-                String::from("morning")
-            },
-            10 | 11 => {
-                String::from("day")
-            },
-            12 | 13 => {
-                String::from("noon")
-            },
-            14 | 15 | 16 => {
-                String::from("afternoon")
-            },
-            17 | 18 => {
-                String::from("sunset")
-            },
-            19 | 20 => {
-                String::from("evening")
-            },
-            21 | 22 => {
-                String::from("night")
-            },
-            23 | 0 => {
-                String::from("late night")
-            },
-            _ => panic!("Unreachable")
-        }
+// Unsplash won't take a query together with collections or topics, so `query` is only used without them
+pub fn random_url(base_url: &str, query: Option<&str>, collections: &[&str], topics: &[&str], filters: &UnsplashFilters) -> String {
+    let mut url = format!("{}/photos/random?count={}", base_url, MAXIMUM_RANDOM_COUNT);
+    if !collections.is_empty() {
+        url.push_str(&format!("&collections={}", collections.join(",")));
+    }
+    if !topics.is_empty() {
+        url.push_str(&format!("&topics={}", topics.join(",")));
+    }
+    if let (Some(query), true) = (query, collections.is_empty() && topics.is_empty()) {
+        url.push_str(&format!("&query={}", query));
+    }
+    if let Some(orientation) = &filters.orientation {
+        url.push_str(orientation_param(orientation));
+    }
+    if let Some(content_filter) = &filters.content_filter {
+        url.push_str(match content_filter {
+            ContentFilter::Low => "&content_filter=low",
+            ContentFilter::High => "&content_filter=high"
+        });
+    }
+    url
+}
+
+// None when nothing matches, which Unsplash answers with a 404
+pub fn random_photos(client: &Client, base_url: &str, query: Option<&str>, collections: &[&str], topics: &[&str], filters: &UnsplashFilters) -> Result<Option<Vec<SearchResult>>, Box<dyn Error>> {
+    let mut response = unsplash_get(client, &random_url(base_url, query, collections, topics, filters))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
     }
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    let data: Vec<SearchResult> = serde_json::from_str(&data)?;
+    Ok(Some(data))
+}
+
+// One page of a collection, topic or someone's likes, along with how many photos there are in all
+pub fn list_photos(client: &Client, base_url: &str, list: &UnsplashList, page: usize, orientation: Option<&Orientation>) -> Result<(Vec<SearchResult>, usize), Box<dyn Error>> {
+    let mut url = format!("{}{}?page={}&per_page={}",
+        base_url,
+        list_path(list),
+        page,
+        MAXIMUM_LIST_PER_PAGE);
+    if let Some(orientation) = orientation {
+        url.push_str(orientation_param(orientation));
+    }
+    let mut response = unsplash_get(client, &url)?;
+    let total: Option<usize> = response.headers().get("X-Total")
+        .and_then(|total| total.to_str().ok())
+        .and_then(|total| total.parse().ok());
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    let data: Vec<SearchResult> = serde_json::from_str(&data)?;
+    let total = total.unwrap_or(data.len());
+    Ok((data, total))
+}
+
+pub fn get_photo(client: &Client, base_url: &str, id: &str) -> Result<SearchResult, Box<dyn Error>> {
+    let mut response = unsplash_get(client, &format!("{}/photos/{}", base_url, id))?;
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    let data: SearchResult = serde_json::from_str(&data)?;
+    Ok(data)
+}
+
+pub(crate) fn track_download(client: &Client, download_location: &str) -> Result<(), Box<dyn Error>> {
+    unsplash_get(client, download_location)?.error_for_status()?;
+    Ok(())
+}
+
+// Returns the format the server says it sent
+pub(crate) fn save_url(client: &Client, url: &str, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+    let mut response = client.get(url).send()?;
+    let format = response.headers().get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(ImageFormat::from_content_type);
+    let mut data = Vec::new();
+    response.read_to_end(&mut data)?;
+    std::fs::write(path, data)?;
+    Ok(format)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
-
-    use chrono::{Local, Timelike};
+    use std::io::ErrorKind;
 
     use super::*;
 
     #[test]
+    #[ignore = "needs an Unsplash key in config.json, see tests/offline.rs"]
     fn test_client() {
         assert!(make_unsplash_client(&Config::from_path(DEFAULT_CONFIG_PATH).unwrap()).is_ok());
     }
 
     #[test]
+    #[ignore = "needs an Unsplash key in config.json, see tests/offline.rs"]
     fn test_search() {
         let client = make_unsplash_client(&Config::from_path(DEFAULT_CONFIG_PATH).unwrap()).unwrap();
-        assert!(search_photos(&client, "noon").is_ok());
+        assert!(search_photos(&client, API_BASE_URL, "noon", &UnsplashFilters::default(), 1).is_ok());
     }
 
     #[test]
-    #[ignore]
-    fn test_download() {
-        let fake_result = SearchResult {
-            id: String::from("6GHNuQAVC8Y"),
-            urls: Urls {
-                raw: String::new(),
-                full: String::from("https://images.unsplash.com/photo-1616762897553-c3a04bcf795d?ixid=MnwyMjk2MjV8MHwxfHNlYXJjaHwxfHxub29ufGVufDB8fHx8MTYyMDU2NzEzNA\\u0026ixlib=rb-1.2.1"),
-                regular: String::new(),
-                small: String::new(),
-                thumb: String::new()
-            }
+    fn test_search_url() {
+        assert_eq!(search_url(API_BASE_URL, "noon", &UnsplashFilters::default()), format!("{}/search/photos?query=noon&per_page=100", API_BASE_URL));
+        let filters = UnsplashFilters {
+            orientation: Some(Orientation::Landscape),
+            color: Some(UnsplashColor::BlackAndWhite),
+            content_filter: Some(ContentFilter::High),
+            order_by: Some(UnsplashOrder::Latest),
+            lang: Some(String::from("de"))
+        };
+        assert_eq!(search_url(API_BASE_URL, "nacht", &filters), format!("{}/search/photos?query=nacht&per_page=100&orientation=landscape&color=black_and_white&content_filter=high&order_by=latest&lang=de", API_BASE_URL));
+    }
+
+    #[test]
+    fn test_random_url() {
+        let filters = UnsplashFilters {
+            orientation: Some(Orientation::Portrait),
+            color: Some(UnsplashColor::Red),
+            ..UnsplashFilters::default()
+        };
+        assert_eq!(random_url(API_BASE_URL, Some("noon"), &[], &[], &filters), format!("{}/photos/random?count=30&query=noon&orientation=portrait", API_BASE_URL));
+        assert_eq!(random_url(API_BASE_URL, Some("noon"), &["1065976", "3330448"], &["nature"], &UnsplashFilters::default()), format!("{}/photos/random?count=30&collections=1065976,3330448&topics=nature", API_BASE_URL));
+    }
+
+    #[test]
+    fn test_search_result() {
+        let data = r##"{
+            "total": 133,
+            "total_pages": 2,
+            "results": [{
+                "id": "eOLpJytrbsQ",
+                "created_at": "2014-11-18T14:35:36-05:00",
+                "width": 4000,
+                "height": 3000,
+                "color": "#A7A2A1",
+                "blur_hash": "LaLXMa9Fx[D%~q%MtQM|kDRjtRIU",
+                "likes": 286,
+                "description": "A man drinking a coffee.",
+                "alt_description": "man holding cup",
+                "user": {
+                    "id": "Ul0QVz12Goo",
+                    "username": "ugmonk",
+                    "name": "Jeff Sheldon",
+                    "links": {
+                        "self": "https://api.unsplash.com/users/ugmonk",
+                        "html": "http://unsplash.com/@ugmonk"
+                    }
+                },
+                "urls": {
+                    "raw": "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f",
+                    "full": "https://hd.unsplash.com/photo-1416339306562-f3d12fefd36f",
+                    "regular": "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-0.3.5&q=80&fm=jpg&crop=entropy&s=92f3e02f63678acc8416d044e189f515",
+                    "small": "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-0.3.5&q=80&fm=jpg&crop=entropy&w=400&fit=max&s=263af33585f9d32af39d165b000845eb",
+                    "thumb": "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-0.3.5&q=80&fm=jpg&crop=entropy&w=200&fit=max&s=8aae34cf35df31a592f0bef16e6342ef"
+                },
+                "links": {
+                    "self": "https://api.unsplash.com/photos/eOLpJytrbsQ",
+                    "html": "http://unsplash.com/photos/eOLpJytrbsQ",
+                    "download": "http://unsplash.com/photos/eOLpJytrbsQ/download",
+                    "download_location": "https://api.unsplash.com/photos/eOLpJytrbsQ/download?ixid=MnwyMjk2MjV8MHwxfHNlYXJjaHwx"
+                }
+            }]
+        }"##;
+        let results: SearchResults = serde_json::from_str(data).unwrap();
+        assert_eq!(results.total_pages, 2);
+        let result = &results.results[0];
+        assert_eq!((result.width, result.height), (4000, 3000));
+        assert_eq!(result.alt_description.as_deref(), Some("man holding cup"));
+        assert_eq!(result.links.download_location, "https://api.unsplash.com/photos/eOLpJytrbsQ/download?ixid=MnwyMjk2MjV8MHwxfHNlYXJjaHwx");
+        assert_eq!(result.user.links.html, "http://unsplash.com/@ugmonk");
+        assert_eq!(result.attribution(), "Photo by Jeff Sheldon on Unsplash");
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimit::from_headers(&headers), None);
+        headers.insert("X-Ratelimit-Limit", HeaderValue::from_static("50"));
+        assert_eq!(RateLimit::from_headers(&headers), None);
+        headers.insert("X-Ratelimit-Remaining", HeaderValue::from_static("7"));
+        assert_eq!(RateLimit::from_headers(&headers), Some(RateLimit { limit: 50, remaining: 7 }));
+        headers.insert("X-Ratelimit-Remaining", HeaderValue::from_static("lots"));
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+
+    #[test]
+    fn test_fit() {
+        let urls = Urls {
+            raw: String::from("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixid=MnwyMjk2MjV8MHwxfHNlYXJjaHwx&ixlib=rb-1.2.1"),
+            full: String::from("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1&q=85&fm=jpg"),
+            regular: String::new(),
+            small: String::new(),
+            thumb: String::new()
+        };
+        let resolution = Resolution { width: 2560, height: 1440, dpr: 1.5 };
+        assert_eq!(urls.fit(&resolution).unwrap(), "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixid=MnwyMjk2MjV8MHwxfHNlYXJjaHwx&ixlib=rb-1.2.1&w=2560&h=1440&fit=crop&dpr=1.5&q=80");
+        assert_eq!(urls.get(&DownloadQuality::FitToDisplay), urls.full);
+        let urls = Urls {
+            raw: String::from("https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg"),
+            ..urls
         };
-        let path = download_photo(&make_unsplash_client(&Config::from_path(DEFAULT_CONFIG_PATH).unwrap()).unwrap(), &fake_result, DownloadQuality::Full).unwrap();
-        assert!(Path::new(&path).exists());
+        assert_eq!(urls.fit(&resolution), None);
+    }
+
+    #[test]
+    fn test_imgix_format() {
+        assert_eq!(imgix_format("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1&q=85&fm=jpg&crop=entropy", ImageFormat::Webp).unwrap(),
+            "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1&q=85&crop=entropy&fm=webp");
+        assert_eq!(imgix_format("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f", ImageFormat::Avif).unwrap(),
+            "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?fm=avif");
+        assert_eq!(imgix_format("https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg", ImageFormat::Webp), None);
+    }
+
+    #[test]
+    fn test_list_path() {
+        assert_eq!(list_path(&UnsplashList::Collection(String::from("1065976"))), "/collections/1065976/photos");
+        assert_eq!(list_path(&UnsplashList::Topic(String::from("nature"))), "/topics/nature/photos");
+        assert_eq!(list_path(&UnsplashList::Likes(String::from("42yeah"))), "/users/42yeah/likes");
+    }
+
+    #[test]
+    fn test_pexels_results() {
+        let data = r#"{
+            "total_results": 1,
+            "page": 1,
+            "per_page": 80,
+            "photos": [{
+                "id": 2014422,
+                "width": 3024,
+                "height": 3024,
+                "photographer": "Joey Farina",
+                "src": {
+                    "original": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg",
+                    "large2x": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&dpr=2&h=650&w=940",
+                    "large": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&h=650&w=940",
+                    "medium": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&h=350",
+                    "small": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&h=130",
+                    "portrait": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&fit=crop&h=1200&w=800",
+                    "landscape": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&fit=crop&h=627&w=1200",
+                    "tiny": "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg?auto=compress&cs=tinysrgb&dpr=1&fit=crop&h=200&w=280"
+                }
+            }]
+        }"#;
+        let results: PexelsResults = serde_json::from_str(data).unwrap();
+        assert_eq!(results.photos[0].id, 2014422);
+        assert_eq!(results.photos[0].attribution().as_deref(), Some("Photo by Joey Farina on Pexels"));
+        let urls = Urls::from(results.photos[0].src.clone());
+        assert_eq!(urls.get(&DownloadQuality::Raw), "https://images.pexels.com/photos/2014422/pexels-photo-2014422.jpeg");
+        assert!(urls.get(&DownloadQuality::Small).ends_with("h=350"));
     }
 
     #[test]
@@ -201,10 +673,4 @@ mod tests {
             }
         }
     }
-
-    #[test]
-    #[ignore = "It might not be 23:00 now"]
-    fn test_now() {
-        assert_eq!(Hour(Local::now().hour()).to_string(), "late night");
-    }
 }
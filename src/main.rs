@@ -1,6 +1,6 @@
 use std::{cell::{Ref, RefCell}, rc::Rc};
 
-use awc::{Config, DownloadQuality, DEFAULT_CONFIG_PATH, Message, MetaMessage, Worker, State};
+use awc::{Config, Desktop, DownloadQuality, FitMode, WallpaperTarget, DEFAULT_CONFIG_PATH, Message, MetaMessage, Worker, State};
 use serde::{Serialize, Deserialize};
 use web_view::Content;
 
@@ -32,26 +32,54 @@ fn main() {
         .debug(true)
         .user_data(config.clone())
         .invoke_handler(|web_view, arg| {
-            let cmd: Cmd = serde_json::from_str(arg).unwrap();
+            let cmd: Cmd = match serde_json::from_str(arg) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    eprintln!("Could not understand the request from the config form: {}. Skipping...", e);
+                    return Ok(());
+                }
+            };
 
             match cmd {
                 Cmd::Init => {
                     let config: Rc<RefCell<Config>> = config.clone();
                     let config: Ref<Config> = config.borrow();
                     match web_view.eval(&format!("
+                    loadedConfig = {};
                     document.querySelector('#repeat-secs').value = {};
                     document.querySelector('#wallpaper-interval').value = {};
                     document.querySelector('#unsplash-access-key').value = '{}';
+                    document.querySelector('#unsplash-mode').value = loadedConfig.unsplash_mode;
+                    document.querySelector('#unsplash-orientation').value = loadedConfig.unsplash_filters.orientation || '';
+                    document.querySelector('#unsplash-color').value = loadedConfig.unsplash_filters.color || '';
+                    document.querySelector('#unsplash-content-filter').value = loadedConfig.unsplash_filters.content_filter || '';
+                    document.querySelector('#unsplash-order-by').value = loadedConfig.unsplash_filters.order_by || '';
+                    document.querySelector('#unsplash-lang').value = loadedConfig.unsplash_filters.lang || '';
+                    document.querySelector('#pexels-access-key').value = '{}';
                     document.querySelector('#openweather-access-key').value = '{}';
                     document.querySelector('#city').value = '{}'
+                    document.querySelector('#time-buckets').value = formatBuckets(loadedConfig.time_buckets);
                     document.querySelector('#quality').value = '{}';
-                    document.querySelector('#cache').checked = {}",
+                    document.querySelector('#image-format').value = loadedConfig.image_format || '';
+                    document.querySelector('#fit').value = '{}';
+                    document.querySelector('#target').value = '{}';
+                    document.querySelector('#distinct-lock-screen').checked = {};
+                    document.querySelector('#desktop').value = '{}';
+                    document.querySelector('#setter-command').value = {};
+                    document.querySelector('#export-dir').value = {};
+                    document.querySelector('#cache').checked = {};
+                    document.querySelector('#per-output').checked = {}",
+                    serde_json::to_string(&*config).unwrap(),
                     config.repeat_secs,
                     config.update_interval,
                     match &config.unsplash_access_key {
                         Some(x) => x,
                         None => ""
                     },
+                    match &config.pexels_access_key {
+                        Some(x) => x,
+                        None => ""
+                    },
                     match &config.openweather_access_key {
                         Some(x) => x,
                         None => ""
@@ -62,12 +90,48 @@ fn main() {
                         DownloadQuality::Full => "Full",
                         DownloadQuality::Regular => "Regular",
                         DownloadQuality::Small => "Small",
-                        DownloadQuality::Thumb => "Thumb"
+                        DownloadQuality::Thumb => "Thumb",
+                        DownloadQuality::FitToDisplay => "FitToDisplay"
+                    },
+                    match &config.fit {
+                        FitMode::Fill => "Fill",
+                        FitMode::Fit => "Fit",
+                        FitMode::Center => "Center",
+                        FitMode::Tile => "Tile",
+                        FitMode::Stretch => "Stretch",
+                        FitMode::Span => "Span"
                     },
+                    match &config.target {
+                        WallpaperTarget::Desktop => "Desktop",
+                        WallpaperTarget::LockScreen => "LockScreen",
+                        WallpaperTarget::Both => "Both"
+                    },
+                    config.distinct_lock_screen,
+                    match &config.desktop {
+                        Some(Desktop::Gnome) => "Gnome",
+                        Some(Desktop::Kde) => "Kde",
+                        Some(Desktop::Xfce) => "Xfce",
+                        Some(Desktop::Mate) => "Mate",
+                        Some(Desktop::Cinnamon) => "Cinnamon",
+                        Some(Desktop::Feh) => "Feh",
+                        Some(Desktop::Nitrogen) => "Nitrogen",
+                        Some(Desktop::Xwallpaper) => "Xwallpaper",
+                        Some(Desktop::Sway) => "Sway",
+                        Some(Desktop::Swaybg) => "Swaybg",
+                        Some(Desktop::Swww) => "Swww",
+                        Some(Desktop::Hyprpaper) => "Hyprpaper",
+                        None => ""
+                    },
+                    serde_json::to_string(&match &config.setter_command {
+                        Some(x) => serde_json::to_string(x).unwrap(),
+                        None => String::new()
+                    }).unwrap(),
+                    serde_json::to_string(config.export_dir.as_deref().unwrap_or("")).unwrap(),
                     match &config.disable_cache {
                         true => "false",
                         false => "true"
-                    })) {
+                    },
+                    config.per_output)) {
                         Ok(_) => {}
                         Err(e) => {
                             eprintln!("There are some errors while filling the blanks: {}. The HTML config form will not be filled.", e);
@@ -107,13 +171,22 @@ fn main() {
                     }
                 },
                 Cmd::UpdateState => {
+                    let attributions: Vec<String> = match &*worker.info.lock().unwrap() {
+                        Some(info) => info.photos.iter().filter_map(|photo| photo.attribution.clone()).collect(),
+                        None => Vec::new()
+                    };
+                    let credit = serde_json::to_string(&attributions.join(", ")).unwrap();
+                    let budget = match &*worker.rate_limit.lock().unwrap() {
+                        Some(rate_limit) => format!("\"{} of {} Unsplash requests left this hour.\"", rate_limit.remaining, rate_limit.limit),
+                        None => String::from("\"\"")
+                    };
                     let state = worker.state.lock().unwrap();
                     match *state {
                         State::Idle | State::Running => {
-                            web_view.eval("render(true)").unwrap();
+                            web_view.eval(&format!("render(true, {}, {})", credit, budget)).unwrap();
                         },
                         State::Stopped => {
-                            web_view.eval("render(false)").unwrap();
+                            web_view.eval(&format!("render(false, {}, {})", credit, budget)).unwrap();
                         }
                     }
                     drop(state);
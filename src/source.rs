@@ -0,0 +1,481 @@
+use std::{collections::HashMap, error::Error, fs::{self, create_dir}, io::ErrorKind, sync::Mutex};
+
+use rand::{Rng, distributions::{Distribution, WeightedIndex}};
+use reqwest::blocking::Client;
+use serde::{Serialize, Deserialize};
+
+use crate::{Config, DownloadQuality, ImageFormat, MAXIMUM_LIST_PER_PAGE, Orientation, RateLimit, Resolution, SearchResult, UnsplashCurated, UnsplashFilters, UnsplashList, UnsplashMode, Urls, config::{SourceConfig, SourceKind}, format::{check_image, find_saved, store_download}, get_pexels_photo, get_photo, imgix_format, list_path, list_photos, make_pexels_client, make_unsplash_client, random_photos, save_url, track_download, search_pexels, search_photos, search_url, unsplash_api_url, unsplash_rate_limit};
+
+mod local;
+mod daily;
+mod reddit;
+mod feed;
+mod json;
+#[cfg(test)]
+mod stub;
+
+pub use local::LocalSource;
+pub use daily::{ApodSource, BingSource, WikimediaSource};
+pub use reddit::RedditSource;
+pub use feed::FeedSource;
+pub use json::{JsonFields, JsonSource};
+
+// Reddit, Wikimedia and many feed hosts turn away clients without a descriptive user agent
+pub(crate) const USER_AGENT: &str = "awc (Automagic Wallpaper Changer)";
+
+// A photo found by one of the sources, with a link for every `DownloadQuality`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Photo {
+    pub id: String,
+    pub source: String,
+    pub attribution: Option<String>,
+    // Unsplash wants to hear about every photo of theirs that gets used, through this link
+    #[serde(default)]
+    pub download_location: Option<String>,
+    pub urls: Urls
+}
+
+// What to look for, `text` being the time of day and weather plus any extra terms
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub text: String,
+    // The time of day bucket alone, e.g. "late night"
+    pub bucket: String,
+    pub orientation: Option<Orientation>
+}
+
+impl Query {
+    pub fn new(text: &str) -> Query {
+        Query {
+            text: String::from(text),
+            bucket: String::new(),
+            orientation: None
+        }
+    }
+}
+
+pub trait PhotoSource {
+    // Used to tell apart photos, and their cached files, coming from different sources
+    fn name(&self) -> &str;
+
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>>;
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>>;
+
+    // Returns the format the photo came in, if the source knows. Otherwise it's told by its first bytes
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>>;
+
+    // How many requests are left, for sources with an hourly budget
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
+}
+
+pub struct UnsplashSource {
+    client: Client,
+    base_url: String,
+    filters: UnsplashFilters,
+    mode: UnsplashMode,
+    max_pages: usize,
+    lists: Vec<UnsplashCurated>,
+    // Page counts by search URL, learned from earlier responses
+    pages: Mutex<HashMap<String, usize>>
+}
+
+impl UnsplashSource {
+    pub fn new(config: &Config, lists: Vec<UnsplashCurated>) -> Result<UnsplashSource, Box<dyn Error>> {
+        Ok(UnsplashSource {
+            client: make_unsplash_client(config)?,
+            base_url: unsplash_api_url(config),
+            filters: config.unsplash_filters.clone(),
+            mode: config.unsplash_mode.clone(),
+            max_pages: config.unsplash_max_pages,
+            lists,
+            pages: Mutex::new(HashMap::new())
+        })
+    }
+
+    // Picks a random page, since the first one is all we'd ever see otherwise. Only the first
+    // time around does it take an extra request to find out how many pages there are
+    fn random_page(&self, key: String, fetch: impl Fn(usize) -> Result<(Vec<SearchResult>, usize), Box<dyn Error>>) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        let known = self.pages.lock().unwrap().get(&key).copied();
+        let page = match known {
+            Some(pages) => pick_page(pages, self.max_pages, &mut rand::thread_rng()),
+            None => 1
+        };
+        let (results, pages) = fetch(page)?;
+        self.pages.lock().unwrap().insert(key, pages);
+        if known.is_none() {
+            let page = pick_page(pages, self.max_pages, &mut rand::thread_rng());
+            if page != 1 {
+                return Ok(fetch(page)?.0);
+            }
+        }
+        Ok(results)
+    }
+
+    fn search_photos(&self, query: &str, filters: &UnsplashFilters) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        self.random_page(search_url(&self.base_url, query, filters), |page| {
+            let results = search_photos(&self.client, &self.base_url, query, filters, page)?;
+            Ok((results.results, results.total_pages))
+        })
+    }
+
+    fn list(&self, list: &UnsplashList, orientation: Option<&Orientation>) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        self.random_page(format!("{}?{:?}", list_path(list), orientation), |page| {
+            let (results, total) = list_photos(&self.client, &self.base_url, list, page, orientation)?;
+            Ok((results, total.div_ceil(MAXIMUM_LIST_PER_PAGE)))
+        })
+    }
+
+    fn search_or_list(&self, text: &str, filters: &UnsplashFilters, lists: &[&UnsplashCurated]) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        if lists.is_empty() {
+            return self.search_photos(text, filters);
+        }
+        let curated = lists[rand::thread_rng().gen_range(0..lists.len())];
+        self.list(&curated.list, filters.orientation.as_ref())
+    }
+
+    // Collections and topics go along as filters, likes can only be paged through
+    fn random(&self, text: &str, filters: &UnsplashFilters, lists: &[&UnsplashCurated]) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        let collections: Vec<&str> = lists.iter().filter_map(|curated| match &curated.list {
+            UnsplashList::Collection(id) => Some(id.as_str()),
+            _ => None
+        }).collect();
+        let topics: Vec<&str> = lists.iter().filter_map(|curated| match &curated.list {
+            UnsplashList::Topic(slug) => Some(slug.as_str()),
+            _ => None
+        }).collect();
+        if !lists.is_empty() && collections.is_empty() && topics.is_empty() {
+            return self.search_or_list(text, filters, lists);
+        }
+        match random_photos(&self.client, &self.base_url, Some(text), &collections, &topics, filters)? {
+            Some(results) => Ok(results),
+            None => {
+                println!("Unsplash has no random photos for {}, searching instead...", text);
+                self.search_or_list(text, filters, lists)
+            }
+        }
+    }
+}
+
+// Anywhere from 1 to `pages`, but no further than `max_pages`
+fn pick_page<R: Rng>(pages: usize, max_pages: usize, rng: &mut R) -> usize {
+    let pages = pages.min(max_pages).max(1);
+    rng.gen_range(1..=pages)
+}
+
+fn unsplash_photo(result: SearchResult) -> Photo {
+    Photo {
+        attribution: Some(result.attribution()),
+        download_location: Some(result.links.download_location).filter(|link| !link.is_empty()),
+        id: result.id,
+        source: String::from("unsplash"),
+        urls: result.urls
+    }
+}
+
+fn lists_for<'a>(lists: &'a [UnsplashCurated], bucket: &str) -> Vec<&'a UnsplashCurated> {
+    lists.iter()
+        .filter(|curated| curated.buckets.is_empty() || curated.buckets.iter().any(|name| name.eq_ignore_ascii_case(bucket)))
+        .collect()
+}
+
+impl PhotoSource for UnsplashSource {
+    fn name(&self) -> &str {
+        "unsplash"
+    }
+
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        // The configured orientation wins over the display's
+        let filters = UnsplashFilters {
+            orientation: self.filters.orientation.clone().or_else(|| query.orientation.clone()),
+            ..self.filters.clone()
+        };
+        let lists = lists_for(&self.lists, &query.bucket);
+        let results = match self.mode {
+            UnsplashMode::Search => self.search_or_list(&query.text, &filters, &lists)?,
+            UnsplashMode::Random => self.random(&query.text, &filters, &lists)?
+        };
+        Ok(results.into_iter().map(unsplash_photo).collect())
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        Ok(unsplash_photo(get_photo(&self.client, &self.base_url, id)?))
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        let format = save_url(&self.client, photo.urls.get(quality), path)?;
+        if let Some(download_location) = &photo.download_location {
+            if let Err(e) = track_download(&self.client, download_location) {
+                eprintln!("Failed to let Unsplash know about {}: {}. Skipping...", photo.id, e);
+            }
+        }
+        Ok(format)
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        unsplash_rate_limit()
+    }
+}
+
+pub struct PexelsSource {
+    client: Client
+}
+
+impl PexelsSource {
+    pub fn new(config: &Config) -> Result<PexelsSource, Box<dyn Error>> {
+        Ok(PexelsSource {
+            client: make_pexels_client(config)?
+        })
+    }
+}
+
+impl PhotoSource for PexelsSource {
+    fn name(&self) -> &str {
+        "pexels"
+    }
+
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let results = search_pexels(&self.client, &query.text, query.orientation.as_ref())?;
+        Ok(results.photos.into_iter().map(|photo| Photo {
+            id: photo.id.to_string(),
+            source: String::from(self.name()),
+            attribution: photo.attribution(),
+            download_location: None,
+            urls: photo.src.into()
+        }).collect())
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        let photo = get_pexels_photo(&self.client, id)?;
+        Ok(Photo {
+            id: photo.id.to_string(),
+            source: String::from(self.name()),
+            attribution: photo.attribution(),
+            download_location: None,
+            urls: photo.src.into()
+        })
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+pub struct WeightedSource {
+    pub source: Box<dyn PhotoSource>,
+    pub weight: u32
+}
+
+pub fn make_photo_source(source_config: &SourceConfig, config: &Config) -> Result<Box<dyn PhotoSource>, Box<dyn Error>> {
+    match &source_config.kind {
+        SourceKind::Unsplash { lists } => Ok(Box::new(UnsplashSource::new(config, lists.clone())?)),
+        SourceKind::Pexels => Ok(Box::new(PexelsSource::new(config)?)),
+        SourceKind::Local { dirs, match_any } => Ok(Box::new(LocalSource::new(dirs, *match_any)?)),
+        SourceKind::Bing { market } => Ok(Box::new(BingSource::new(market.as_deref())?)),
+        SourceKind::Wikimedia => Ok(Box::new(WikimediaSource::new()?)),
+        SourceKind::Apod { api_key } => Ok(Box::new(ApodSource::new(api_key.as_deref())?)),
+        SourceKind::Reddit { subreddit, sort, time, min_width, min_height } => {
+            Ok(Box::new(RedditSource::new(reddit::REDDIT_URL, subreddit, sort.clone(), time.clone(), (*min_width, *min_height))?))
+        },
+        SourceKind::Feed { url } => Ok(Box::new(FeedSource::new(url)?)),
+        SourceKind::Json { name, url, headers, results, id, image, attribution } => {
+            Ok(Box::new(JsonSource::new(name.as_deref().unwrap_or("json"), url, headers, JsonFields {
+                results: results.clone(),
+                id: id.clone(),
+                image: image.clone(),
+                attribution: attribution.clone()
+            })?))
+        }
+    }
+}
+
+// Sources that can't be set up, e.g. for lack of a key, are left out
+pub fn make_photo_sources(config: &Config) -> Vec<WeightedSource> {
+    let mut sources = Vec::new();
+    for source_config in &config.sources {
+        if source_config.weight == 0 {
+            continue;
+        }
+        match make_photo_source(source_config, config) {
+            Ok(source) => sources.push(WeightedSource {
+                source,
+                weight: source_config.weight
+            }),
+            Err(e) => eprintln!("Failed to prepare {:?} source: {}. Skipping...", source_config.kind, e)
+        }
+    }
+    sources
+}
+
+// Picks sources at random by weight, without repeating, so the ones left over can serve as fallbacks
+pub(crate) fn weighted_order(weights: &[u32], rng: &mut impl Rng) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..weights.len()).filter(|&i| weights[i] > 0).collect();
+    let mut order = Vec::new();
+    while !remaining.is_empty() {
+        let index = match WeightedIndex::new(remaining.iter().map(|&i| weights[i])) {
+            Ok(distribution) => distribution.sample(rng),
+            Err(_) => 0
+        };
+        order.push(remaining.remove(index));
+    }
+    order
+}
+
+// Ids that can't go into a file name as they are get hashed, with 64-bit FNV-1a rather than std's
+// hasher, which may change between Rust releases and leave the cache behind
+pub(crate) fn file_safe_id(id: &str) -> String {
+    if !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return String::from(id);
+    }
+    let hash = id.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+// With `FitToDisplay`, photos that can be resized come in as a raw URL asking for the display's size
+pub(crate) fn fit_to_display(photo: &Photo, quality: &DownloadQuality, resolution: Option<&Resolution>) -> (Photo, DownloadQuality) {
+    let url = match (quality, resolution) {
+        (DownloadQuality::FitToDisplay, Some(resolution)) => photo.urls.fit(resolution),
+        _ => None
+    };
+    match url {
+        Some(url) => (Photo {
+            urls: Urls {
+                raw: url,
+                ..photo.urls.clone()
+            },
+            ..photo.clone()
+        }, DownloadQuality::Raw),
+        None => (photo.clone(), quality.clone())
+    }
+}
+
+// Asks for the photo in `format` where the source can convert it, which only Unsplash does
+pub(crate) fn request_format(photo: &Photo, quality: &DownloadQuality, format: ImageFormat) -> (Photo, DownloadQuality) {
+    match imgix_format(photo.urls.get(quality), format) {
+        Some(url) => (Photo {
+            urls: Urls {
+                raw: url,
+                ..photo.urls.clone()
+            },
+            ..photo.clone()
+        }, DownloadQuality::Raw),
+        None => (photo.clone(), quality.clone())
+    }
+}
+
+// Names such as a `Json` source's come from the config, so they're made safe like ids
+fn cache_stem(dir: &str, source: &str, id: &str) -> String {
+    format!("{}/{}-{}", dir, file_safe_id(source), id)
+}
+
+// Downloads `photo` into the cache directory `dir` unless it's already there. It's saved with the
+// extension of the format it turned out to be in, converted to one of `formats` when it's in none of them
+pub fn download_cached(source: &dyn PhotoSource, photo: &Photo, quality: &DownloadQuality, formats: &[ImageFormat], dir: &str) -> Result<String, Box<dyn Error>> {
+    let stem = cache_stem(dir, source.name(), &photo.id);
+    if let Some(save_path) = find_saved(&stem) {
+        // A bad file from before gets quarantined and downloaded again
+        if let Ok(save_path) = check_image(save_path) {
+            return Ok(save_path);
+        }
+    }
+    if let Err(e) = create_dir(dir) {
+        if e.kind() != ErrorKind::AlreadyExists {
+            return Err(Box::new(e));
+        }
+    }
+    let part = format!("{}.part", stem);
+    let hint = match source.download(photo, quality, &part) {
+        Ok(hint) => hint,
+        Err(e) => {
+            let _ = fs::remove_file(&part);
+            return Err(e);
+        }
+    };
+    check_image(store_download(&part, &stem, hint, formats)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    fn test_weighted_order() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let mut order = weighted_order(&[3, 0, 1], &mut rng);
+            order.sort();
+            assert_eq!(order, vec![0, 2]);
+        }
+        let firsts = (0..1000).filter(|_| weighted_order(&[9, 1], &mut rng)[0] == 0).count();
+        assert!(firsts > 800, "{} out of 1000", firsts);
+        assert!(weighted_order(&[], &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_lists_for() {
+        let lists: Vec<UnsplashCurated> = serde_json::from_str(r#"[
+            {"buckets": ["night", "late night"], "collection": "1065976"},
+            {"buckets": ["noon"], "topic": "nature"},
+            {"likes": "42yeah"}
+        ]"#).unwrap();
+        assert_eq!(lists[0].list, UnsplashList::Collection(String::from("1065976")));
+        assert_eq!(lists_for(&lists, "Late Night"), vec![&lists[0], &lists[2]]);
+        assert_eq!(lists_for(&lists, "noon"), vec![&lists[1], &lists[2]]);
+        assert_eq!(lists_for(&lists[..2], "sunrise"), Vec::<&UnsplashCurated>::new());
+        assert_eq!(serde_json::to_string(&lists[1]).unwrap(), r#"{"buckets":["noon"],"topic":"nature"}"#);
+    }
+
+    #[test]
+    fn test_pick_page() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            assert!((1..=10).contains(&pick_page(334, 10, &mut rng)));
+            assert!((1..=3).contains(&pick_page(3, 10, &mut rng)));
+        }
+        assert_eq!(pick_page(0, 10, &mut rng), 1);
+        assert_eq!(pick_page(50, 0, &mut rng), 1);
+    }
+
+    #[test]
+    fn test_fit_to_display() {
+        let photo = Photo {
+            id: String::from("eOLpJytrbsQ"),
+            source: String::from("unsplash"),
+            attribution: None,
+            download_location: None,
+            urls: Urls {
+                raw: String::from("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1"),
+                full: String::from("https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1&q=85"),
+                regular: String::new(),
+                small: String::new(),
+                thumb: String::new()
+            }
+        };
+        let resolution = Resolution { width: 1080, height: 1920, dpr: 2.0 };
+        let (sized, quality) = fit_to_display(&photo, &DownloadQuality::FitToDisplay, Some(&resolution));
+        assert!(matches!(quality, DownloadQuality::Raw));
+        assert_eq!(sized.urls.get(&quality), "https://images.unsplash.com/photo-1416339306562-f3d12fefd36f?ixlib=rb-1.2.1&w=1080&h=1920&fit=crop&dpr=2&q=80");
+        let (full, quality) = fit_to_display(&photo, &DownloadQuality::FitToDisplay, None);
+        assert_eq!(full.urls.get(&quality), photo.urls.full);
+        let (small, quality) = fit_to_display(&photo, &DownloadQuality::Small, Some(&resolution));
+        assert!(matches!(quality, DownloadQuality::Small));
+        assert_eq!(small.urls.raw, photo.urls.raw);
+    }
+
+    #[test]
+    fn test_file_safe_id() {
+        assert_eq!(file_safe_id("6GHNuQAVC8Y"), "6GHNuQAVC8Y");
+        assert_eq!(file_safe_id("2021-05-09"), "2021-05-09");
+        assert_eq!(file_safe_id("../night/1042").len(), 16);
+        // Cached names have to stay the same from one build to the next
+        assert_eq!(file_safe_id(""), "cbf29ce484222325");
+        assert_eq!(file_safe_id("a/b"), "e620c3190468cf61");
+        assert_ne!(file_safe_id("a/b"), file_safe_id("a/c"));
+        assert_eq!(cache_stem("download", "unsplash", "6GHNuQAVC8Y"), "download/unsplash-6GHNuQAVC8Y");
+        assert_eq!(cache_stem("download", "../work/api", "1041").matches('/').count(), 1);
+    }
+}
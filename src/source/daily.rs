@@ -0,0 +1,335 @@
+use std::{error::Error, io::{self, ErrorKind, Read}};
+
+use chrono::{Duration, Local, NaiveDate};
+use reqwest::blocking::Client;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{DownloadQuality, ImageFormat, Orientation, Urls, save_url};
+use super::{Photo, PhotoSource, Query, USER_AGENT};
+
+const BING_URL: &str = "https://www.bing.com/HPImageArchive.aspx";
+const WIKIMEDIA_FEED_URL: &str = "https://api.wikimedia.org/feed/v1/wikipedia/en/featured";
+const APOD_URL: &str = "https://api.nasa.gov/planetary/apod";
+const APOD_DEMO_KEY: &str = "DEMO_KEY";
+// Bing keeps about a week of images, and APOD rarely has more than a few videos in a row
+const DAYS_BACK: i64 = 7;
+
+fn get_json<T: DeserializeOwned>(client: &Client, url: &str) -> Result<T, Box<dyn Error>> {
+    let mut response = client.get(url).send()?;
+    let mut data = String::new();
+    response.read_to_string(&mut data)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn not_found(message: String) -> Box<dyn Error> {
+    Box::new(io::Error::new(ErrorKind::NotFound, message))
+}
+
+#[derive(Deserialize)]
+struct BingImage {
+    startdate: String,
+    urlbase: String
+}
+
+#[derive(Deserialize)]
+struct BingArchive {
+    images: Vec<BingImage>
+}
+
+// Bing image of the day, in the sizes it serves for every image
+pub struct BingSource {
+    client: Client,
+    market: String
+}
+
+impl BingSource {
+    pub fn new(market: Option<&str>) -> Result<BingSource, Box<dyn Error>> {
+        Ok(BingSource {
+            client: Client::builder().user_agent(USER_AGENT).build()?,
+            market: String::from(market.unwrap_or("en-US"))
+        })
+    }
+
+    fn archive(&self, count: i64) -> Result<BingArchive, Box<dyn Error>> {
+        get_json(&self.client, &format!("{}?format=js&idx=0&n={}&mkt={}", BING_URL, count, self.market))
+    }
+}
+
+fn bing_photo(image: &BingImage, portrait: bool) -> Photo {
+    let base = format!("https://www.bing.com{}", image.urlbase);
+    let sizes = if portrait {
+        ["1080x1920", "1080x1920", "768x1366", "480x800", "240x320"]
+    } else {
+        ["UHD", "1920x1080", "1366x768", "800x480", "320x240"]
+    };
+    let url = |size: &str| format!("{}_{}.jpg", base, size);
+    Photo {
+        id: if portrait { format!("{}-portrait", image.startdate) } else { image.startdate.clone() },
+        source: String::from("bing"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: url(sizes[0]),
+            full: url(sizes[1]),
+            regular: url(sizes[2]),
+            small: url(sizes[3]),
+            thumb: url(sizes[4])
+        }
+    }
+}
+
+impl PhotoSource for BingSource {
+    fn name(&self) -> &str {
+        "bing"
+    }
+
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let portrait = query.orientation == Some(Orientation::Portrait);
+        Ok(self.archive(1)?.images.iter().map(|image| bing_photo(image, portrait)).collect())
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        let (date, portrait) = match id.strip_suffix("-portrait") {
+            Some(date) => (date, true),
+            None => (id, false)
+        };
+        match self.archive(DAYS_BACK)?.images.iter().find(|image| image.startdate == date) {
+            Some(image) => Ok(bing_photo(image, portrait)),
+            None => Err(not_found(format!("Bing has no image for {} anymore", date)))
+        }
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[derive(Deserialize)]
+struct WikimediaThumbnail {
+    source: String
+}
+
+#[derive(Deserialize)]
+struct WikimediaOriginal {
+    source: String,
+    #[serde(default)]
+    width: Option<u32>
+}
+
+#[derive(Deserialize)]
+struct WikimediaImage {
+    thumbnail: WikimediaThumbnail,
+    image: WikimediaOriginal
+}
+
+#[derive(Deserialize)]
+struct WikimediaFeed {
+    image: Option<WikimediaImage>
+}
+
+// Wikimedia Commons picture of the day, through the featured content feed
+pub struct WikimediaSource {
+    client: Client
+}
+
+impl WikimediaSource {
+    pub fn new() -> Result<WikimediaSource, Box<dyn Error>> {
+        Ok(WikimediaSource {
+            client: Client::builder().user_agent(USER_AGENT).build()?
+        })
+    }
+
+    fn photo_on(&self, date: NaiveDate) -> Result<Photo, Box<dyn Error>> {
+        let feed: WikimediaFeed = get_json(&self.client, &format!("{}/{}", WIKIMEDIA_FEED_URL, date.format("%Y/%m/%d")))?;
+        match feed.image {
+            Some(image) => Ok(wikimedia_photo(&date.format("%Y-%m-%d").to_string(), &image)),
+            None => Err(not_found(format!("There's no picture of the day for {}", date)))
+        }
+    }
+}
+
+// Thumbnails look like ".../thumb/a/ab/Name.jpg/640px-Name.jpg", and any other width can be
+// asked for by changing the prefix. The widths follow Unsplash's, but never go past the original's
+fn wikimedia_photo(id: &str, image: &WikimediaImage) -> Photo {
+    let thumbnail = &image.thumbnail.source;
+    let url = |width: u32| -> String {
+        let width = image.image.width.map_or(width, |original| width.min(original));
+        match (thumbnail.rfind('/'), thumbnail.find("px-")) {
+            (Some(slash), Some(px)) if px > slash => format!("{}/{}{}", &thumbnail[..slash], width, &thumbnail[px..]),
+            _ => thumbnail.clone()
+        }
+    };
+    Photo {
+        id: String::from(id),
+        source: String::from("wikimedia"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: image.image.source.clone(),
+            full: url(2560),
+            regular: url(1080),
+            small: url(400),
+            thumb: url(200)
+        }
+    }
+}
+
+impl PhotoSource for WikimediaSource {
+    fn name(&self) -> &str {
+        "wikimedia"
+    }
+
+    fn search(&self, _query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        Ok(vec![self.photo_on(Local::now().naive_local().date())?])
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        self.photo_on(NaiveDate::parse_from_str(id, "%Y-%m-%d")?)
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[derive(Deserialize)]
+struct ApodEntry {
+    date: String,
+    media_type: String,
+    url: Option<String>,
+    hdurl: Option<String>
+}
+
+// NASA's Astronomy Picture of the Day, which works with `DEMO_KEY` at a low rate limit
+pub struct ApodSource {
+    client: Client,
+    api_key: String
+}
+
+impl ApodSource {
+    pub fn new(api_key: Option<&str>) -> Result<ApodSource, Box<dyn Error>> {
+        Ok(ApodSource {
+            client: Client::builder().user_agent(USER_AGENT).build()?,
+            api_key: String::from(api_key.unwrap_or(APOD_DEMO_KEY))
+        })
+    }
+}
+
+// Some days are videos, these are skipped in favour of the latest picture
+fn latest_apod(entries: &[ApodEntry]) -> Option<Photo> {
+    entries.iter()
+        .filter(|entry| entry.media_type == "image")
+        .max_by(|a, b| a.date.cmp(&b.date))
+        .and_then(apod_photo)
+}
+
+fn apod_photo(entry: &ApodEntry) -> Option<Photo> {
+    let url = entry.url.clone()?;
+    let hdurl = entry.hdurl.clone().unwrap_or_else(|| url.clone());
+    Some(Photo {
+        id: entry.date.clone(),
+        source: String::from("apod"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: hdurl.clone(),
+            full: hdurl,
+            regular: url.clone(),
+            small: url.clone(),
+            thumb: url
+        }
+    })
+}
+
+impl PhotoSource for ApodSource {
+    fn name(&self) -> &str {
+        "apod"
+    }
+
+    fn search(&self, _query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let start = Local::now().naive_local().date() - Duration::days(DAYS_BACK);
+        let entries: Vec<ApodEntry> = get_json(&self.client, &format!("{}?api_key={}&start_date={}",
+            APOD_URL,
+            self.api_key,
+            start.format("%Y-%m-%d")))?;
+        match latest_apod(&entries) {
+            Some(photo) => Ok(vec![photo]),
+            None => Err(not_found(format!("APOD has had no pictures since {}", start)))
+        }
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        let entry: ApodEntry = get_json(&self.client, &format!("{}?api_key={}&date={}", APOD_URL, self.api_key, id))?;
+        match latest_apod(&[entry]) {
+            Some(photo) => Ok(photo),
+            None => Err(not_found(format!("APOD for {} is not a picture", id)))
+        }
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bing() {
+        let data = r#"{"images": [{
+            "startdate": "20210509",
+            "fullstartdate": "202105090700",
+            "url": "/th?id=OHR.MothersDay_EN-US1234567890_1920x1080.jpg&rf=LaDigue_1920x1080.jpg&pid=hp",
+            "urlbase": "/th?id=OHR.MothersDay_EN-US1234567890",
+            "copyright": "Mother bear and cubs (© Someone)",
+            "title": "Info"
+        }]}"#;
+        let archive: BingArchive = serde_json::from_str(data).unwrap();
+        let photo = bing_photo(&archive.images[0], false);
+        assert_eq!(photo.id, "20210509");
+        assert_eq!(photo.urls.get(&DownloadQuality::Raw), "https://www.bing.com/th?id=OHR.MothersDay_EN-US1234567890_UHD.jpg");
+        assert_eq!(photo.urls.get(&DownloadQuality::Regular), "https://www.bing.com/th?id=OHR.MothersDay_EN-US1234567890_1366x768.jpg");
+        let photo = bing_photo(&archive.images[0], true);
+        assert_eq!(photo.id, "20210509-portrait");
+        assert_eq!(photo.urls.get(&DownloadQuality::Full), "https://www.bing.com/th?id=OHR.MothersDay_EN-US1234567890_1080x1920.jpg");
+    }
+
+    #[test]
+    fn test_wikimedia() {
+        let data = r#"{"tfa": {}, "image": {
+            "title": "File:Lake Louise.jpg",
+            "thumbnail": {"source": "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Lake_Louise.jpg/640px-Lake_Louise.jpg", "width": 640, "height": 427},
+            "image": {"source": "https://upload.wikimedia.org/wikipedia/commons/a/ab/Lake_Louise.jpg", "width": 6000, "height": 4000}
+        }}"#;
+        let feed: WikimediaFeed = serde_json::from_str(data).unwrap();
+        let photo = wikimedia_photo("2021-05-09", feed.image.as_ref().unwrap());
+        assert_eq!(photo.urls.get(&DownloadQuality::Raw), "https://upload.wikimedia.org/wikipedia/commons/a/ab/Lake_Louise.jpg");
+        assert_eq!(photo.urls.get(&DownloadQuality::Full), "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Lake_Louise.jpg/2560px-Lake_Louise.jpg");
+        assert_eq!(photo.urls.get(&DownloadQuality::Thumb), "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Lake_Louise.jpg/200px-Lake_Louise.jpg");
+        // Smaller originals aren't scaled up
+        let feed: WikimediaFeed = serde_json::from_str(&data.replace(r#""width": 6000"#, r#""width": 1600"#)).unwrap();
+        let photo = wikimedia_photo("2021-05-09", feed.image.as_ref().unwrap());
+        assert_eq!(photo.urls.get(&DownloadQuality::Full), "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Lake_Louise.jpg/1600px-Lake_Louise.jpg");
+        assert_eq!(photo.urls.get(&DownloadQuality::Regular), "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ab/Lake_Louise.jpg/1080px-Lake_Louise.jpg");
+        let feed: WikimediaFeed = serde_json::from_str(r#"{"tfa": {}}"#).unwrap();
+        assert!(feed.image.is_none());
+    }
+
+    #[test]
+    fn test_apod_skips_videos() {
+        let data = r#"[
+            {"date": "2021-05-07", "media_type": "image", "title": "M51", "url": "https://apod.nasa.gov/apod/image/2105/M51.jpg", "hdurl": "https://apod.nasa.gov/apod/image/2105/M51_big.jpg"},
+            {"date": "2021-05-08", "media_type": "image", "title": "Moon", "url": "https://apod.nasa.gov/apod/image/2105/Moon.jpg"},
+            {"date": "2021-05-09", "media_type": "video", "title": "Launch", "url": "https://www.youtube.com/embed/abc"}
+        ]"#;
+        let entries: Vec<ApodEntry> = serde_json::from_str(data).unwrap();
+        let photo = latest_apod(&entries).unwrap();
+        assert_eq!(photo.id, "2021-05-08");
+        assert_eq!(photo.urls.get(&DownloadQuality::Raw), "https://apod.nasa.gov/apod/image/2105/Moon.jpg");
+        let photo = latest_apod(&entries[..1]).unwrap();
+        assert_eq!(photo.urls.get(&DownloadQuality::Full), "https://apod.nasa.gov/apod/image/2105/M51_big.jpg");
+        assert_eq!(photo.urls.get(&DownloadQuality::Small), "https://apod.nasa.gov/apod/image/2105/M51.jpg");
+        assert!(latest_apod(&entries[2..]).is_none());
+    }
+}
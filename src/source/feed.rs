@@ -0,0 +1,257 @@
+use std::{error::Error, io::Read};
+
+use reqwest::blocking::Client;
+use roxmltree::{Document, Node};
+
+use crate::{DownloadQuality, ImageFormat, Urls, save_url};
+use super::{Photo, PhotoSource, Query, USER_AGENT, file_safe_id};
+
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+const EXTENSIONS: &[&str] = &[".jpg", ".jpeg", ".png", ".webp"];
+
+#[derive(Debug, PartialEq)]
+struct Image {
+    url: String,
+    width: Option<u32>
+}
+
+// Images found in an RSS 2.0 or Atom feed, through enclosures and Media RSS
+pub struct FeedSource {
+    client: Client,
+    url: String
+}
+
+impl FeedSource {
+    pub fn new(url: &str) -> Result<FeedSource, Box<dyn Error>> {
+        Ok(FeedSource {
+            client: Client::builder().user_agent(USER_AGENT).build()?,
+            url: String::from(url)
+        })
+    }
+
+    fn items(&self) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let mut response = self.client.get(&self.url).send()?.error_for_status()?;
+        let mut data = String::new();
+        response.read_to_string(&mut data)?;
+        parse_feed(&data)
+    }
+}
+
+fn is_media(node: &Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name && node.tag_name().namespace() == Some(MEDIA_NAMESPACE)
+}
+
+fn is_image(mime: Option<&str>, medium: Option<&str>, url: &str) -> bool {
+    if let Some(medium) = medium {
+        return medium == "image";
+    }
+    match mime {
+        Some(mime) => mime.starts_with("image/"),
+        None => {
+            let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+            EXTENSIONS.iter().any(|extension| path.ends_with(extension))
+        }
+    }
+}
+
+// Every image attached to an <item> or <entry>, including those inside <media:group>
+fn item_images(item: &Node) -> (Vec<Image>, Option<String>) {
+    let mut images = Vec::new();
+    let mut thumbnail = None;
+    for node in item.descendants().filter(|node| node.is_element()) {
+        let name = node.tag_name().name();
+        if is_media(&node, "content") {
+            if let Some(url) = node.attribute("url") {
+                if is_image(node.attribute("type"), node.attribute("medium"), url) {
+                    images.push(Image {
+                        url: String::from(url),
+                        width: node.attribute("width").and_then(|width| width.parse().ok())
+                    });
+                }
+            }
+        } else if is_media(&node, "thumbnail") {
+            if thumbnail.is_none() {
+                thumbnail = node.attribute("url").map(String::from);
+            }
+        } else if name == "enclosure" {
+            if let Some(url) = node.attribute("url") {
+                if is_image(node.attribute("type"), None, url) {
+                    images.push(Image { url: String::from(url), width: None });
+                }
+            }
+        } else if name == "link" && node.attribute("rel") == Some("enclosure") {
+            if let Some(url) = node.attribute("href") {
+                if is_image(node.attribute("type"), None, url) {
+                    images.push(Image { url: String::from(url), width: None });
+                }
+            }
+        }
+    }
+    (images, thumbnail)
+}
+
+fn child_text(item: &Node, name: &str) -> Option<String> {
+    item.children()
+        .find(|node| node.is_element() && node.tag_name().name() == name)
+        .and_then(|node| node.text())
+        .map(|text| String::from(text.trim()))
+        .filter(|text| !text.is_empty())
+}
+
+// Images without a width are assumed to be full size
+fn item_photo(item: &Node) -> Option<Photo> {
+    let (mut images, thumbnail) = item_images(item);
+    if images.is_empty() {
+        return None;
+    }
+    images.sort_by_key(|image| std::cmp::Reverse(image.width.unwrap_or(u32::MAX)));
+    let largest = images[0].url.clone();
+    let sized = |width: u32| -> String {
+        images.iter()
+            .find(|image| image.width.is_some_and(|image_width| image_width <= width))
+            .map(|image| image.url.clone())
+            .unwrap_or_else(|| largest.clone())
+    };
+    let key = child_text(item, "guid")
+        .or_else(|| child_text(item, "id"))
+        .unwrap_or_else(|| largest.clone());
+    Some(Photo {
+        id: file_safe_id(&key),
+        source: String::from("feed"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: largest.clone(),
+            full: largest.clone(),
+            regular: sized(1080),
+            small: sized(640),
+            thumb: thumbnail.unwrap_or_else(|| sized(320))
+        }
+    })
+}
+
+fn parse_feed(data: &str) -> Result<Vec<Photo>, Box<dyn Error>> {
+    let document = Document::parse(data)?;
+    let root = document.root_element();
+    let items: Vec<Node> = match root.tag_name().name() {
+        // RSS 2.0 keeps items in <channel>, RSS 1.0 next to it
+        "rss" | "RDF" => root.descendants().filter(|node| node.is_element() && node.tag_name().name() == "item").collect(),
+        "feed" => root.children().filter(|node| node.is_element() && node.tag_name().name() == "entry").collect(),
+        other => return Err(format!("<{}> is neither an RSS nor an Atom feed", other).into())
+    };
+    Ok(items.iter().filter_map(item_photo).collect())
+}
+
+impl PhotoSource for FeedSource {
+    fn name(&self) -> &str {
+        "feed"
+    }
+
+    // Feeds are curated already, so the query is ignored
+    fn search(&self, _query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        self.items()
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        match self.items()?.into_iter().find(|photo| photo.id == id) {
+            Some(photo) => Ok(photo),
+            None => Err(format!("{} is no longer in {}", id, self.url).into())
+        }
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::stub::StubServer;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/">
+  <channel>
+    <title>Photo of the week</title>
+    <item>
+      <title>Harbour at night</title>
+      <guid>https://photos.example.com/harbour</guid>
+      <enclosure url="https://photos.example.com/harbour.jpg" length="2500000" type="image/jpeg"/>
+    </item>
+    <item>
+      <title>Podcast episode</title>
+      <enclosure url="https://photos.example.com/episode.mp3" length="9000000" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title>Mountains</title>
+      <guid>mountains</guid>
+      <media:group>
+        <media:content url="https://photos.example.com/mountains-640.jpg" medium="image" width="640"/>
+        <media:content url="https://photos.example.com/mountains-4000.jpg" medium="image" width="4000"/>
+        <media:content url="https://photos.example.com/mountains-1080.jpg" medium="image" width="1080"/>
+        <media:content url="https://photos.example.com/mountains.mp4" medium="video"/>
+      </media:group>
+      <media:thumbnail url="https://photos.example.com/mountains-thumb.jpg"/>
+    </item>
+    <item>
+      <title>Just text</title>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/">
+  <title>Agency wire</title>
+  <entry>
+    <id>tag:agency.example.com,2021:1</id>
+    <title>Forest in fog</title>
+    <link rel="alternate" href="https://agency.example.com/forest"/>
+    <link rel="enclosure" type="image/png" href="https://agency.example.com/forest.png"/>
+  </entry>
+  <entry>
+    <id>tag:agency.example.com,2021:2</id>
+    <title>Desert</title>
+    <media:content url="https://agency.example.com/desert.jpg?size=full"/>
+  </entry>
+  <entry>
+    <id>tag:agency.example.com,2021:3</id>
+    <title>Article</title>
+    <link rel="alternate" href="https://agency.example.com/article"/>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn test_rss() {
+        let photos = parse_feed(RSS).unwrap();
+        assert_eq!(photos.len(), 2);
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Raw), "https://photos.example.com/harbour.jpg");
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Thumb), "https://photos.example.com/harbour.jpg");
+        assert_eq!(photos[1].id, file_safe_id("mountains"));
+        assert_eq!(photos[1].urls.get(&DownloadQuality::Full), "https://photos.example.com/mountains-4000.jpg");
+        assert_eq!(photos[1].urls.get(&DownloadQuality::Regular), "https://photos.example.com/mountains-1080.jpg");
+        assert_eq!(photos[1].urls.get(&DownloadQuality::Small), "https://photos.example.com/mountains-640.jpg");
+        assert_eq!(photos[1].urls.get(&DownloadQuality::Thumb), "https://photos.example.com/mountains-thumb.jpg");
+    }
+
+    #[test]
+    fn test_atom() {
+        let photos = parse_feed(ATOM).unwrap();
+        let urls: Vec<&str> = photos.iter().map(|photo| photo.urls.get(&DownloadQuality::Full)).collect();
+        assert_eq!(urls, vec!["https://agency.example.com/forest.png", "https://agency.example.com/desert.jpg?size=full"]);
+        assert_eq!(photos[0].id, file_safe_id("tag:agency.example.com,2021:1"));
+        assert!(parse_feed("<html><body/></html>").is_err());
+        assert!(parse_feed("not xml").is_err());
+    }
+
+    #[test]
+    fn test_feed_source() {
+        let server = StubServer::start();
+        server.route("/feed.xml", "application/rss+xml", RSS.as_bytes());
+        let source = FeedSource::new(&format!("{}/feed.xml", server.url)).unwrap();
+        let photos = source.search(&Query::new("noon")).unwrap();
+        assert_eq!(photos.len(), 2);
+        assert!(server.headers()[0].contains(&format!("user-agent: {}", USER_AGENT.to_lowercase())));
+        assert_eq!(source.photo(&photos[1].id).unwrap().urls.get(&DownloadQuality::Raw), "https://photos.example.com/mountains-4000.jpg");
+        assert!(FeedSource::new(&format!("{}/missing.xml", server.url)).unwrap().search(&Query::new("noon")).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, error::Error, io::Read};
+
+use reqwest::{blocking::Client, header::{HeaderMap, HeaderName, HeaderValue}};
+use serde_json::Value;
+
+use crate::{DownloadQuality, ImageFormat, Urls, save_url};
+use super::{Photo, PhotoSource, Query, USER_AGENT, file_safe_id};
+
+// Where things are in the response, as paths like "data.items", "urls.full" or "images[0].url"
+pub struct JsonFields {
+    pub results: String,
+    pub id: String,
+    pub image: String,
+    pub attribution: Option<String>
+}
+
+// Any HTTP API answering with JSON, described by a URL template and `JsonFields`
+pub struct JsonSource {
+    client: Client,
+    name: String,
+    url: String,
+    fields: JsonFields
+}
+
+impl JsonSource {
+    pub fn new(name: &str, url: &str, headers: &BTreeMap<String, String>, fields: JsonFields) -> Result<JsonSource, Box<dyn Error>> {
+        let mut default_headers = HeaderMap::new();
+        for (name, value) in headers {
+            default_headers.append(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+        Ok(JsonSource {
+            client: Client::builder().user_agent(USER_AGENT).default_headers(default_headers).build()?,
+            name: String::from(name),
+            url: String::from(url),
+            fields
+        })
+    }
+
+    fn to_photo(&self, result: &Value) -> Option<Photo> {
+        let id = match lookup(result, &self.fields.id)? {
+            Value::String(id) => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => return None
+        };
+        let image = lookup(result, &self.fields.image)?.as_str()?;
+        let attribution = self.fields.attribution.as_ref()
+            .and_then(|path| lookup(result, path))
+            .and_then(|attribution| attribution.as_str())
+            .map(String::from);
+        Some(Photo {
+            id: file_safe_id(&id),
+            source: self.name.clone(),
+            attribution,
+            download_location: None,
+            urls: Urls {
+                raw: String::from(image),
+                full: String::from(image),
+                regular: String::from(image),
+                small: String::from(image),
+                thumb: String::from(image)
+            }
+        })
+    }
+
+    fn results(&self, url: &str) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let mut response = self.client.get(url).send()?.error_for_status()?;
+        let mut data = String::new();
+        response.read_to_string(&mut data)?;
+        let data: Value = serde_json::from_str(&data)?;
+        let results = match lookup(&data, &self.fields.results) {
+            Some(Value::Array(results)) => results,
+            _ => return Err(format!("`{}` is not an array in the response", self.fields.results).into())
+        };
+        Ok(results.iter().filter_map(|result| self.to_photo(result)).collect())
+    }
+}
+
+// "a.b[0].c", "a.b.0.c" and "$.a" all work, an empty path or "$" is the value itself
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim_start_matches('$').trim_start_matches('.');
+    let mut current = value;
+    for segment in path.replace('[', ".").replace(']', "").split('.').filter(|segment| !segment.is_empty()) {
+        current = match current {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            Value::Object(fields) => fields.get(segment)?,
+            _ => return None
+        };
+    }
+    Some(current)
+}
+
+// Percent-encodes everything but unreserved characters
+fn encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte)
+    }).collect()
+}
+
+impl PhotoSource for JsonSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        self.results(&self.url.replace("{query}", &encode(&query.text)))
+    }
+
+    // There's no telling how to look up a single photo, so it has to turn up in the results again
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        match self.results(&self.url.replace("{query}", ""))?.into_iter().find(|photo| photo.id == id) {
+            Some(photo) => Ok(photo),
+            None => Err(format!("{} is not among the results of {}", id, self.name).into())
+        }
+    }
+
+    // Only one image URL is known, whatever the quality
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::stub::StubServer;
+
+    const RESPONSE: &str = r#"{
+        "meta": {"count": 3},
+        "data": {
+            "items": [
+                {"key": 1041, "media": [{"href": "https://images.example.com/1041.jpg"}], "credit": {"name": "Ada"}},
+                {"key": "night/1042", "media": [{"href": "https://images.example.com/1042.jpg"}]},
+                {"key": 1043, "media": []}
+            ]
+        }
+    }"#;
+
+    fn fields() -> JsonFields {
+        JsonFields {
+            results: String::from("data.items"),
+            id: String::from("key"),
+            image: String::from("media[0].href"),
+            attribution: Some(String::from("credit.name"))
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let data: Value = serde_json::from_str(RESPONSE).unwrap();
+        assert_eq!(lookup(&data, "meta.count"), Some(&Value::from(3)));
+        assert_eq!(lookup(&data, "$.data.items.1.key"), Some(&Value::from("night/1042")));
+        assert_eq!(lookup(&data, "data.items[0].media[0].href"), Some(&Value::from("https://images.example.com/1041.jpg")));
+        assert_eq!(lookup(&data, "data.items[5]"), None);
+        assert_eq!(lookup(&data, "meta.count.nope"), None);
+        assert_eq!(lookup(&data, "$"), Some(&data));
+        assert_eq!(encode("late night Rain/ü"), "late%20night%20Rain%2F%C3%BC");
+    }
+
+    #[test]
+    fn test_json_source() {
+        let server = StubServer::start();
+        server.route("/api/images", "application/json", RESPONSE.as_bytes());
+        let mut headers = BTreeMap::new();
+        headers.insert(String::from("X-Api-Key"), String::from("secret"));
+        let source = JsonSource::new("work", &format!("{}/api/images?q={{query}}&limit=50", server.url), &headers, fields()).unwrap();
+        let photos = source.search(&Query::new("late night")).unwrap();
+        assert_eq!(server.requests(), vec!["/api/images?q=late%20night&limit=50"]);
+        assert!(server.headers()[0].contains(&String::from("x-api-key: secret")));
+        assert!(server.headers()[0].contains(&format!("user-agent: {}", USER_AGENT.to_lowercase())));
+
+        assert_eq!(photos.len(), 2);
+        assert_eq!(photos[0].id, "1041");
+        assert_eq!(photos[0].source, "work");
+        assert_eq!(photos[0].attribution.as_deref(), Some("Ada"));
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Thumb), "https://images.example.com/1041.jpg");
+        assert_ne!(photos[1].id, "night/1042");
+        assert_eq!(photos[1].attribution, None);
+        assert_eq!(source.photo(&photos[1].id).unwrap().urls.get(&DownloadQuality::Raw), "https://images.example.com/1042.jpg");
+
+        let source = JsonSource::new("work", &format!("{}/api/images", server.url), &BTreeMap::new(), JsonFields {
+            results: String::from("meta"),
+            ..fields()
+        }).unwrap();
+        assert!(source.search(&Query::new("noon")).is_err());
+    }
+}
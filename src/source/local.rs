@@ -0,0 +1,224 @@
+use std::{error::Error, fs, io::{self, ErrorKind}, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::{DownloadQuality, ImageFormat, Urls};
+use super::{Photo, PhotoSource, Query};
+
+const EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "bmp", "webp"];
+
+// Sidecar files may list tags directly or under "tags"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Sidecar {
+    Tags(Vec<String>),
+    Object { tags: Vec<String> }
+}
+
+struct LocalPhoto {
+    id: String,
+    path: PathBuf,
+    tags: Vec<String>
+}
+
+// Photos from folders on disk, tagged by their subdirectories, file names and sidecar files,
+// e.g. `night/rain/harbour.jpg` next to an optional `harbour.json` or `harbour.txt`
+pub struct LocalSource {
+    dirs: Vec<PathBuf>,
+    match_any: bool
+}
+
+impl LocalSource {
+    pub fn new(dirs: &[String], match_any: bool) -> Result<LocalSource, io::Error> {
+        if dirs.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "A local source needs at least one directory in `dirs`"));
+        }
+        Ok(LocalSource {
+            dirs: dirs.iter().map(PathBuf::from).collect(),
+            match_any
+        })
+    }
+
+    fn scan(&self) -> Result<Vec<LocalPhoto>, io::Error> {
+        let mut photos = Vec::new();
+        for (index, dir) in self.dirs.iter().enumerate() {
+            scan_dir(index, dir, dir, &mut photos)?;
+        }
+        Ok(photos)
+    }
+}
+
+fn scan_dir(index: usize, root: &Path, dir: &Path, photos: &mut Vec<LocalPhoto>) -> Result<(), io::Error> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            scan_dir(index, root, &path, photos)?;
+            continue;
+        }
+        let is_image = path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        if !is_image {
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let mut tags = Vec::new();
+        for component in relative.parent().into_iter().flat_map(|parent| parent.iter()) {
+            tags.extend(words(&component.to_string_lossy()));
+        }
+        if let Some(stem) = path.file_stem() {
+            tags.extend(words(&stem.to_string_lossy()));
+        }
+        tags.extend(sidecar_tags(&path));
+        let id: String = relative.to_string_lossy()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        photos.push(LocalPhoto {
+            id: format!("{}-{}", index, id),
+            path,
+            tags
+        });
+    }
+    Ok(())
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn sidecar_tags(path: &Path) -> Vec<String> {
+    let mut tags = Vec::new();
+    if let Ok(data) = fs::read_to_string(path.with_extension("json")) {
+        match serde_json::from_str(&data) {
+            Ok(Sidecar::Tags(list)) | Ok(Sidecar::Object { tags: list }) => {
+                tags.extend(list.iter().flat_map(|tag| words(tag)));
+            },
+            Err(e) => eprintln!("Could not read tags from {}.json: {}. Skipping...", path.display(), e)
+        }
+    }
+    if let Ok(data) = fs::read_to_string(path.with_extension("txt")) {
+        tags.extend(words(&data));
+    }
+    tags
+}
+
+// "rain" matches "rainy" and "clouds" matches "cloud", but short words have to match exactly
+fn matches(tag: &str, term: &str) -> bool {
+    tag == term || (tag.len() >= 4 && term.len() >= 4 && (tag.starts_with(term) || term.starts_with(tag)))
+}
+
+fn score(photo: &LocalPhoto, terms: &[String]) -> usize {
+    terms.iter().filter(|term| photo.tags.iter().any(|tag| matches(tag, term))).count()
+}
+
+fn to_photo(photo: &LocalPhoto) -> Photo {
+    let path = photo.path.to_string_lossy().into_owned();
+    Photo {
+        id: photo.id.clone(),
+        source: String::from("local"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: path.clone(),
+            full: path.clone(),
+            regular: path.clone(),
+            small: path.clone(),
+            thumb: path
+        }
+    }
+}
+
+impl PhotoSource for LocalSource {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    // Only the photos matching the most query terms are returned
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let photos = self.scan()?;
+        let terms = words(&query.text);
+        let best = photos.iter().map(|photo| score(photo, &terms)).max().unwrap_or(0);
+        if best == 0 && !self.match_any {
+            return Ok(Vec::new());
+        }
+        Ok(photos.iter()
+            .filter(|photo| score(photo, &terms) == best)
+            .map(to_photo)
+            .collect())
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        match self.scan()?.iter().find(|photo| photo.id == id) {
+            Some(photo) => Ok(to_photo(photo)),
+            None => Err(Box::new(io::Error::new(ErrorKind::NotFound, format!("No local photo {}", id))))
+        }
+    }
+
+    // Local photos are the same whatever the quality
+    fn download(&self, photo: &Photo, _quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        fs::copy(&photo.urls.full, path)?;
+        Ok(ImageFormat::from_extension(Path::new(&photo.urls.full)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn photo_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("awc-local-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in &["night/rain", "noon", "misc"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in &["night/rain/harbour.jpg", "night/stars.png", "noon/beach.jpg", "noon/kite.gif", "misc/IMG_0001.jpg", "misc/IMG_0002.JPG"] {
+            fs::copy("ferris.png", dir.join(file)).unwrap();
+        }
+        fs::write(dir.join("misc/notes.md"), "not a photo").unwrap();
+        fs::write(dir.join("misc/IMG_0001.json"), r#"{"tags": ["Sunset", "clouds"]}"#).unwrap();
+        fs::write(dir.join("misc/IMG_0002.txt"), "evening, city lights").unwrap();
+        dir
+    }
+
+    fn ids(photos: Vec<Photo>) -> Vec<String> {
+        photos.into_iter().map(|photo| photo.id).collect()
+    }
+
+    #[test]
+    fn test_tags() {
+        let dir = photo_dir("tags");
+        let source = LocalSource::new(&[dir.to_string_lossy().into_owned()], false).unwrap();
+        assert_eq!(ids(source.search(&Query::new("late night Rain")).unwrap()), vec!["0-night-rain-harbour-jpg"]);
+        assert_eq!(ids(source.search(&Query::new("late night")).unwrap()), vec!["0-night-rain-harbour-jpg", "0-night-stars-png"]);
+        assert_eq!(ids(source.search(&Query::new("evening Clear")).unwrap()), vec!["0-misc-IMG-0002-JPG"]);
+        assert_eq!(ids(source.search(&Query::new("sunset Clouds")).unwrap()), vec!["0-misc-IMG-0001-jpg"]);
+        assert_eq!(ids(source.search(&Query::new("noon Clear")).unwrap()), vec!["0-noon-beach-jpg", "0-noon-kite-gif"]);
+        assert!(source.search(&Query::new("twilight Snow")).unwrap().is_empty());
+
+        let source = LocalSource::new(&[dir.to_string_lossy().into_owned()], true).unwrap();
+        assert_eq!(source.search(&Query::new("twilight Snow")).unwrap().len(), 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_download() {
+        let dir = photo_dir("download");
+        let source = LocalSource::new(&[dir.to_string_lossy().into_owned()], false).unwrap();
+        let photo = source.photo("0-night-stars-png").unwrap();
+        let copy = dir.join("copy.png");
+        source.download(&photo, &DownloadQuality::Full, &copy.to_string_lossy()).unwrap();
+        assert_eq!(fs::read(&copy).unwrap(), fs::read("ferris.png").unwrap());
+        assert!(source.photo("0-missing-jpg").is_err());
+        assert!(LocalSource::new(&[], false).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
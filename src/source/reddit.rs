@@ -0,0 +1,278 @@
+use std::{error::Error, io::Read};
+
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use crate::{DownloadQuality, ImageFormat, Orientation, Urls, config::{RedditSort, RedditTime}, save_url};
+use super::{Photo, PhotoSource, Query, USER_AGENT};
+
+pub const REDDIT_URL: &str = "https://www.reddit.com";
+const MAXIMUM_LIMIT: i32 = 100;
+const EXTENSIONS: &[&str] = &[".jpg", ".jpeg", ".png", ".webp"];
+const IMAGE_HOSTS: &[&str] = &["i.redd.it", "i.imgur.com"];
+
+#[derive(Deserialize)]
+struct PreviewImage {
+    url: String,
+    width: u32,
+    height: u32
+}
+
+#[derive(Deserialize)]
+struct PreviewImages {
+    source: PreviewImage,
+    #[serde(default)]
+    resolutions: Vec<PreviewImage>
+}
+
+#[derive(Deserialize)]
+struct Preview {
+    images: Vec<PreviewImages>
+}
+
+#[derive(Deserialize)]
+struct Post {
+    id: String,
+    title: String,
+    url: String,
+    #[serde(default)]
+    over_18: bool,
+    preview: Option<Preview>
+}
+
+#[derive(Deserialize)]
+struct Child {
+    data: Post
+}
+
+#[derive(Deserialize)]
+struct ListingData {
+    children: Vec<Child>
+}
+
+#[derive(Deserialize)]
+struct Listing {
+    data: ListingData
+}
+
+// Images posted to a subreddit, e.g. r/EarthPorn, skipping NSFW posts and anything that isn't
+// a direct link to an image of at least `min_width` x `min_height`
+pub struct RedditSource {
+    client: Client,
+    base_url: String,
+    subreddit: String,
+    sort: RedditSort,
+    time: RedditTime,
+    min_size: (u32, u32)
+}
+
+impl RedditSource {
+    pub fn new(base_url: &str, subreddit: &str, sort: RedditSort, time: RedditTime, min_size: (u32, u32)) -> Result<RedditSource, Box<dyn Error>> {
+        Ok(RedditSource {
+            client: Client::builder().user_agent(USER_AGENT).build()?,
+            base_url: String::from(base_url.trim_end_matches('/')),
+            subreddit: String::from(subreddit.trim_start_matches("r/")),
+            sort,
+            time,
+            min_size
+        })
+    }
+
+    fn listing(&self, url: &str) -> Result<Vec<Post>, Box<dyn Error>> {
+        let mut response = self.client.get(url).send()?.error_for_status()?;
+        let mut data = String::new();
+        response.read_to_string(&mut data)?;
+        // A single post comes back as a listing of the post followed by one of its comments
+        let listing = match serde_json::from_str(&data)? {
+            serde_json::Value::Array(mut listings) if !listings.is_empty() => listings.swap_remove(0),
+            listing => listing
+        };
+        let listing: Listing = serde_json::from_value(listing)?;
+        Ok(listing.data.children.into_iter().map(|child| child.data).collect())
+    }
+
+    fn accepts(&self, post: &Post, orientation: Option<&Orientation>) -> bool {
+        if post.over_18 || !is_direct_image(&post.url) {
+            return false;
+        }
+        let (width, height) = match size(post) {
+            Some(size) => size,
+            None => return self.min_size == (0, 0) && orientation.is_none()
+        };
+        let orientation_matches = match orientation {
+            Some(Orientation::Landscape) => width > height,
+            Some(Orientation::Portrait) => height > width,
+            Some(Orientation::Squarish) => width.max(height) * 4 <= width.min(height) * 5,
+            None => true
+        };
+        orientation_matches && width >= self.min_size.0 && height >= self.min_size.1
+    }
+}
+
+fn is_direct_image(url: &str) -> bool {
+    let url = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    let host = url.split("://").nth(1).and_then(|rest| rest.split('/').next()).unwrap_or("");
+    IMAGE_HOSTS.contains(&host) || EXTENSIONS.iter().any(|extension| url.ends_with(extension))
+}
+
+// The preview knows the real size, otherwise there's the "[4000x3000]" most image subreddits
+// ask for in titles
+fn size(post: &Post) -> Option<(u32, u32)> {
+    let preview = post.preview.as_ref().and_then(|preview| preview.images.first());
+    match preview {
+        Some(images) => Some((images.source.width, images.source.height)),
+        None => title_size(&post.title)
+    }
+}
+
+fn title_size(title: &str) -> Option<(u32, u32)> {
+    let title: Vec<char> = title.to_lowercase().replace('×', "x").chars().collect();
+    for (i, c) in title.iter().enumerate() {
+        if *c != 'x' {
+            continue;
+        }
+        let mut start = i;
+        while start > 0 && title[start - 1] == ' ' {
+            start -= 1;
+        }
+        let width_end = start;
+        while start > 0 && title[start - 1].is_ascii_digit() {
+            start -= 1;
+        }
+        let mut end = i + 1;
+        while end < title.len() && title[end] == ' ' {
+            end += 1;
+        }
+        let height_start = end;
+        while end < title.len() && title[end].is_ascii_digit() {
+            end += 1;
+        }
+        let width: String = title[start..width_end].iter().collect();
+        let height: String = title[height_start..end].iter().collect();
+        if let (Ok(width), Ok(height)) = (width.parse::<u32>(), height.parse::<u32>()) {
+            // Leaves out things like "2x zoom"
+            if width >= 100 && height >= 100 {
+                return Some((width, height));
+            }
+        }
+    }
+    None
+}
+
+// Previews come in a handful of widths, the ones closest to Unsplash's sizes stand in for the
+// smaller qualities
+fn post_photo(post: &Post) -> Photo {
+    let preview = post.preview.as_ref().and_then(|preview| preview.images.first());
+    let resized = |width: u32| -> String {
+        preview.and_then(|images| images.resolutions.iter()
+                .filter(|resolution| resolution.width <= width)
+                .max_by_key(|resolution| resolution.width)
+                .or_else(|| images.resolutions.first()))
+            .map(|resolution| resolution.url.clone())
+            .unwrap_or_else(|| post.url.clone())
+    };
+    Photo {
+        id: post.id.clone(),
+        source: String::from("reddit"),
+        attribution: None,
+        download_location: None,
+        urls: Urls {
+            raw: post.url.clone(),
+            full: post.url.clone(),
+            regular: resized(1080),
+            small: resized(640),
+            thumb: resized(320)
+        }
+    }
+}
+
+impl PhotoSource for RedditSource {
+    fn name(&self) -> &str {
+        "reddit"
+    }
+
+    // The subreddit is what picks the photos, so the query is ignored
+    fn search(&self, query: &Query) -> Result<Vec<Photo>, Box<dyn Error>> {
+        let sort = match self.sort {
+            RedditSort::Hot => "hot",
+            RedditSort::Top => "top",
+            RedditSort::New => "new"
+        };
+        let time = match self.time {
+            RedditTime::Hour => "hour",
+            RedditTime::Day => "day",
+            RedditTime::Week => "week",
+            RedditTime::Month => "month",
+            RedditTime::Year => "year",
+            RedditTime::All => "all"
+        };
+        let url = format!("{}/r/{}/{}.json?t={}&limit={}&raw_json=1", self.base_url, self.subreddit, sort, time, MAXIMUM_LIMIT);
+        Ok(self.listing(&url)?.iter()
+            .filter(|post| self.accepts(post, query.orientation.as_ref()))
+            .map(post_photo)
+            .collect())
+    }
+
+    fn photo(&self, id: &str) -> Result<Photo, Box<dyn Error>> {
+        let url = format!("{}/comments/{}.json?raw_json=1", self.base_url, id);
+        match self.listing(&url)?.first() {
+            Some(post) => Ok(post_photo(post)),
+            None => Err(format!("There's no post {} on Reddit", id).into())
+        }
+    }
+
+    fn download(&self, photo: &Photo, quality: &DownloadQuality, path: &str) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+        save_url(&self.client, photo.urls.get(quality), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::stub::StubServer;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/reddit_earthporn_top.json");
+
+    fn ids(photos: &[Photo]) -> Vec<&str> {
+        photos.iter().map(|photo| photo.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_title_size() {
+        assert_eq!(title_size("Sunrise over the Dolomites [OC] [4000x3000]"), Some((4000, 3000)));
+        assert_eq!(title_size("Glacier lagoon [5472 × 3648] [OC]"), Some((5472, 3648)));
+        assert_eq!(title_size("Shot at 2x zoom [OC] (1920X1080)"), Some((1920, 1080)));
+        assert_eq!(title_size("Redwoods, California [OC]"), None);
+    }
+
+    #[test]
+    fn test_listing() {
+        let server = StubServer::start();
+        server.route("/r/EarthPorn/top.json", "application/json", FIXTURE.as_bytes());
+        let source = RedditSource::new(&server.url, "r/EarthPorn", RedditSort::Top, RedditTime::Week, (1920, 1080)).unwrap();
+        let photos = source.search(&Query::new("late night Rain")).unwrap();
+        assert_eq!(ids(&photos), vec!["n8a1b2", "n8k1l2", "n8k2f0"]);
+        assert_eq!(server.requests(), vec!["/r/EarthPorn/top.json?t=week&limit=100&raw_json=1"]);
+
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Full), "https://i.redd.it/abcdef123456.jpg");
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Regular), "https://preview.redd.it/abcdef123456.jpg?width=1080&s=5");
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Small), "https://preview.redd.it/abcdef123456.jpg?width=640&s=4");
+        assert_eq!(photos[0].urls.get(&DownloadQuality::Thumb), "https://preview.redd.it/abcdef123456.jpg?width=320&s=3");
+        assert_eq!(photos[1].urls.get(&DownloadQuality::Thumb), "https://i.imgur.com/GlAcIeR.jpeg?1");
+
+        let portrait = source.search(&Query {
+            orientation: Some(Orientation::Portrait),
+            ..Query::new("noon")
+        }).unwrap();
+        assert_eq!(ids(&portrait), vec!["n8k2f0"]);
+        let any_size = RedditSource::new(&server.url, "EarthPorn", RedditSort::Top, RedditTime::Week, (0, 0)).unwrap();
+        assert_eq!(ids(&any_size.search(&Query::new("noon")).unwrap()), vec!["n8a1b2", "n8i9j0", "n8k1l2", "n8k2f0"]);
+    }
+
+    #[test]
+    fn test_missing_subreddit() {
+        let server = StubServer::start();
+        let source = RedditSource::new(&server.url, "nope", RedditSort::Hot, RedditTime::Day, (0, 0)).unwrap();
+        assert!(source.search(&Query::new("noon")).is_err());
+    }
+}
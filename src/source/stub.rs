@@ -0,0 +1,75 @@
+use std::{io::{BufRead, BufReader, Write}, net::TcpListener, sync::{Arc, Mutex}, thread};
+
+type Routes = Arc<Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+// A tiny HTTP server serving canned responses by path, so sources can be tested offline.
+// The integration tests include this file too, so it must not depend on the rest of the crate
+pub struct StubServer {
+    pub url: String,
+    routes: Routes,
+    requests: Arc<Mutex<Vec<String>>>,
+    headers: Arc<Mutex<Vec<Vec<String>>>>
+}
+
+impl StubServer {
+    pub fn start() -> StubServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Routes = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let (served, log, header_log) = (routes.clone(), requests.clone(), headers.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut request_headers = Vec::new();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    request_headers.push(line.trim().to_lowercase());
+                    line.clear();
+                }
+                header_log.lock().unwrap().push(request_headers);
+                let target = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let path = target.split('?').next().unwrap_or("/").to_string();
+                log.lock().unwrap().push(target);
+                let response = match served.lock().unwrap().iter().find(|(route, _, _)| *route == path) {
+                    Some((_, content_type, body)) => {
+                        let mut response = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", content_type, body.len()).into_bytes();
+                        response.extend(body);
+                        response
+                    },
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        StubServer { url, routes, requests, headers }
+    }
+
+    // The query string is ignored when matching, fixtures can point back at the stub by writing `{base}` in place of its URL
+    pub fn route(&self, path: &str, content_type: &str, body: &[u8]) {
+        let body = match std::str::from_utf8(body) {
+            Ok(text) => text.replace("{base}", &self.url).into_bytes(),
+            Err(_) => body.to_vec()
+        };
+        self.routes.lock().unwrap().push((String::from(path), String::from(content_type), body));
+    }
+
+    // Request targets seen so far, including query strings
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // Lowercased header lines of every request so far
+    pub fn headers(&self) -> Vec<Vec<String>> {
+        self.headers.lock().unwrap().clone()
+    }
+}
@@ -1,73 +1,353 @@
+use std::{collections::HashMap, env, io::{self, ErrorKind}, process::Command};
+
+use crate::{Config, config::{FitMode, ImageFormat, Orientation}};
+
+mod export;
+
+pub use export::{ExportSetter, PhotoInfo, WallpaperInfo};
+
+// Tests that read or change PATH and the desktop's environment variables take turns
+#[cfg(test)]
+pub(crate) static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+pub trait WallpaperSetter {
+    fn set_wallpaper(&self, path: &str) -> Result<(), io::Error>;
+
+    // Maps output names to wallpaper paths. Setters that can't tell monitors apart just use one of them
+    fn set_wallpapers(&self, paths: &HashMap<String, String>) -> Result<(), io::Error> {
+        match paths.values().next() {
+            Some(path) => self.set_wallpaper(path),
+            None => Ok(())
+        }
+    }
+
+    fn set_lock_screen(&self, _path: &str) -> Result<(), io::Error> {
+        Err(io::Error::new(ErrorKind::Unsupported, "This setter can't change the lock screen"))
+    }
+
+    // Called once the wallpapers are in place, for setters that care where they came from
+    fn set_info(&self, _info: &WallpaperInfo) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    // What the setter can display, anything else being converted to the first of them
+    fn formats(&self) -> &'static [ImageFormat] {
+        &[ImageFormat::Jpeg, ImageFormat::Png]
+    }
+}
+
+// `width` and `height` are in logical pixels, `scale` times that many on HiDPI screens
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32
+}
+
+impl Output {
+    pub fn orientation(&self) -> Orientation {
+        if self.height > self.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+pub fn list_outputs() -> Result<Vec<Output>, io::Error> {
+    platform::list_outputs()
+}
+
+pub fn make_wallpaper_setter(config: &Config) -> Result<Box<dyn WallpaperSetter>, io::Error> {
+    let setter: Option<Box<dyn WallpaperSetter>> = if config.export_only {
+        None
+    } else if let Some(template) = &config.setter_command {
+        Some(Box::new(CommandSetter::new(template.clone(), config.fit.clone())?))
+    } else {
+        Some(platform::make_setter(config)?)
+    };
+    match (&config.export_dir, setter) {
+        (Some(dir), setter) => Ok(Box::new(ExportSetter::new(dir, setter)?)),
+        (None, Some(setter)) => Ok(setter),
+        (None, None) => Err(io::Error::new(ErrorKind::InvalidInput, "`export_only` needs an `export_dir`"))
+    }
+}
+
+pub(crate) fn absolute_path(path: &str) -> Result<String, io::Error> {
+    let current_dir = env::current_dir()?;
+    Ok(current_dir.join(path).to_string_lossy().into_owned())
+}
+
+// Runs a user supplied command, e.g. ["my-setter", "--file", "{path}", "--mode", "{fit}"].
+// Templates mentioning `{output}` are run once per monitor, and templates mentioning `{target}`
+// are also used for the lock screen
+pub struct CommandSetter {
+    template: Vec<String>,
+    fit: FitMode
+}
+
+impl CommandSetter {
+    pub fn new(template: Vec<String>, fit: FitMode) -> Result<CommandSetter, io::Error> {
+        if template.is_empty() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "`setter_command` must not be empty"));
+        }
+        Ok(CommandSetter { template, fit })
+    }
+
+    fn expand(&self, path: &str, output: &str, target: &str) -> Vec<String> {
+        let fit = match self.fit {
+            FitMode::Fill => "fill",
+            FitMode::Fit => "fit",
+            FitMode::Center => "center",
+            FitMode::Tile => "tile",
+            FitMode::Stretch => "stretch",
+            FitMode::Span => "span"
+        };
+        self.template.iter()
+            .map(|arg| arg.replace("{path}", path)
+                .replace("{output}", output)
+                .replace("{fit}", fit)
+                .replace("{target}", target))
+            .collect()
+    }
+
+    fn run(&self, path: &str, output: &str, target: &str) -> Result<(), io::Error> {
+        let args = self.expand(&absolute_path(path)?, output, target);
+        let status = Command::new(&args[0]).args(&args[1..]).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("{} failed with {}", args[0], status)));
+        }
+        Ok(())
+    }
+}
+
+impl WallpaperSetter for CommandSetter {
+    fn set_wallpaper(&self, path: &str) -> Result<(), io::Error> {
+        self.run(path, "", "desktop")
+    }
+
+    fn set_wallpapers(&self, paths: &HashMap<String, String>) -> Result<(), io::Error> {
+        if !self.template.iter().any(|arg| arg.contains("{output}")) {
+            return match paths.values().next() {
+                Some(path) => self.set_wallpaper(path),
+                None => Ok(())
+            };
+        }
+        for (output, path) in paths {
+            self.run(path, output, "desktop")?;
+        }
+        Ok(())
+    }
+
+    fn set_lock_screen(&self, path: &str) -> Result<(), io::Error> {
+        if !self.template.iter().any(|arg| arg.contains("{target}")) {
+            return Err(io::Error::new(ErrorKind::Unsupported, "`setter_command` doesn't mention {target}"));
+        }
+        self.run(path, "", "lock-screen")
+    }
+}
+
 // Code taken from https://github.com/reujab/wallpaper.rs/blob/master/src/macos.rs
 
 #[cfg(any(target_os = "windows"))]
-pub mod set_wallpaper {
+mod platform {
     use std::{io, env};
     use std::os::windows::ffi::OsStrExt;
     use winapi::um::winuser::SystemParametersInfoW;
     use winapi::um::winuser::SPIF_SENDCHANGE;
     use winapi::um::winuser::SPIF_UPDATEINIFILE;
     use winapi::um::winuser::SPI_SETDESKWALLPAPER;
+    use winapi::um::winreg::{RegSetKeyValueW, HKEY_CURRENT_USER};
+    use winapi::um::winnt::REG_SZ;
+    use winapi::shared::minwindef::{DWORD, LPCVOID};
+    use winapi::shared::winerror::ERROR_SUCCESS;
     use std::os::raw::c_void;
     use std::ffi::OsStr;
     use std::iter;
+    use crate::{Config, config::{FitMode, ImageFormat}};
+    use super::{Output, WallpaperSetter};
 
-    pub fn set_wallpaper(path: &str) -> Result<(), io::Error> {
-        unsafe {
-            let current_dir = env::current_dir()?;
-            let current_dir = current_dir.as_path().to_str().unwrap();
-            let path = OsStr::new(&format!("{}/{}", current_dir, path))
-                .encode_wide()
-                // append null byte
-                .chain(iter::once(0))
-                .collect::<Vec<u16>>();
-            let successful = SystemParametersInfoW(
-                SPI_SETDESKWALLPAPER,
-                0,
-                path.as_ptr() as *mut c_void,
-                SPIF_UPDATEINIFILE | SPIF_SENDCHANGE,
-            ) == 1;
-    
-            if successful {
-                Ok(())
-            } else {
-                Err(io::Error::last_os_error().into())
+    pub struct WindowsSetter {
+        fit: FitMode
+    }
+
+    pub fn make_setter(config: &Config) -> Result<Box<dyn WallpaperSetter>, io::Error> {
+        Ok(Box::new(WindowsSetter {
+            fit: config.fit.clone()
+        }))
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        OsStr::new(s).encode_wide().chain(iter::once(0)).collect()
+    }
+
+    // Writes a string value under HKEY_CURRENT_USER\Control Panel\Desktop
+    fn set_desktop_value(name: &str, value: &str) -> Result<(), io::Error> {
+        let key = wide("Control Panel\\Desktop");
+        let name = wide(name);
+        let value = wide(value);
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                name.as_ptr(),
+                REG_SZ,
+                value.as_ptr() as LPCVOID,
+                (value.len() * 2) as DWORD,
+            )
+        };
+        if status as DWORD != ERROR_SUCCESS {
+            return Err(io::Error::from_raw_os_error(status));
+        }
+        Ok(())
+    }
+
+    impl WindowsSetter {
+        // The style has to be in the registry before the wallpaper is set, which is when it gets picked up
+        fn set_style(&self) -> Result<(), io::Error> {
+            let (style, tile) = match self.fit {
+                FitMode::Center => ("0", "0"),
+                FitMode::Tile => ("0", "1"),
+                FitMode::Stretch => ("2", "0"),
+                FitMode::Fit => ("6", "0"),
+                FitMode::Fill => ("10", "0"),
+                FitMode::Span => ("22", "0")
+            };
+            set_desktop_value("WallpaperStyle", style)?;
+            set_desktop_value("TileWallpaper", tile)
+        }
+    }
+
+    pub fn list_outputs() -> Result<Vec<Output>, io::Error> {
+        Ok(Vec::new())
+    }
+
+    impl WallpaperSetter for WindowsSetter {
+        fn set_wallpaper(&self, path: &str) -> Result<(), io::Error> {
+            self.set_style()?;
+            unsafe {
+                let current_dir = env::current_dir()?;
+                let current_dir = current_dir.as_path().to_str().unwrap();
+                let path = OsStr::new(&format!("{}/{}", current_dir, path))
+                    .encode_wide()
+                    // append null byte
+                    .chain(iter::once(0))
+                    .collect::<Vec<u16>>();
+                let successful = SystemParametersInfoW(
+                    SPI_SETDESKWALLPAPER,
+                    0,
+                    path.as_ptr() as *mut c_void,
+                    SPIF_UPDATEINIFILE | SPIF_SENDCHANGE,
+                ) == 1;
+
+                if successful {
+                    Ok(())
+                } else {
+                    Err(io::Error::last_os_error().into())
+                }
             }
         }
-    }    
+
+        fn formats(&self) -> &'static [ImageFormat] {
+            &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Bmp, ImageFormat::Gif]
+        }
+    }
 }
 
 
-#[cfg(any(target_os = "linux"))]
-pub mod set_wallpaper {
-    pub fn set_wallpaper(path: &str) -> Result<(), io::Error> {
-        unimplemented!();
-    }
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(target_os = "linux")]
+mod wayland;
+
+#[cfg(target_os = "linux")]
+mod outputs;
+
+#[cfg(target_os = "linux")]
+mod platform {
+    pub use super::linux::make_setter;
+    pub use super::outputs::list_outputs;
 }
 
 
 #[cfg(any(target_os = "macos"))]
-pub mod set_wallpaper {
+mod platform {
     use std::{env, io, process::Command};
+    use crate::{Config, config::ImageFormat};
+    use super::{Output, WallpaperSetter};
 
-    pub fn set_wallpaper(path: &str) -> Result<(), io::Error> {
-        let current_dir = env::current_dir()?;
-        let current_dir = current_dir.as_path().to_str().unwrap();
-        Command::new("osascript")
-            .arg("-e")
-            .arg(format!("tell application \"Finder\" to set desktop picture to POSIX file \"{}/{}\"", current_dir, path))
-            .spawn()?;
-        Ok(())
+    pub struct MacOsSetter;
+
+    pub fn make_setter(_config: &Config) -> Result<Box<dyn WallpaperSetter>, io::Error> {
+        Ok(Box::new(MacOsSetter))
+    }
+
+    pub fn list_outputs() -> Result<Vec<Output>, io::Error> {
+        Ok(Vec::new())
+    }
+
+    // Finder has no say over how the picture is scaled, so `fit` is ignored here
+    impl WallpaperSetter for MacOsSetter {
+        fn set_wallpaper(&self, path: &str) -> Result<(), io::Error> {
+            let current_dir = env::current_dir()?;
+            let current_dir = current_dir.as_path().to_str().unwrap();
+            Command::new("osascript")
+                .arg("-e")
+                .arg(format!("tell application \"Finder\" to set desktop picture to POSIX file \"{}/{}\"", current_dir, path))
+                .spawn()?;
+            Ok(())
+        }
+
+        // WebP since Big Sur
+        fn formats(&self) -> &'static [ImageFormat] {
+            &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif, ImageFormat::Bmp, ImageFormat::Webp]
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{Config, DEFAULT_CONFIG_PATH};
     use super::*;
 
     #[test]
-    // #[ignore = "Your wallpaper WILL be changed"]
+    #[ignore = "Your wallpaper WILL be changed"]
     fn test_set_wallpaper() {
-        set_wallpaper::set_wallpaper("ferris.png").unwrap()
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        make_wallpaper_setter(&Config::from_path(DEFAULT_CONFIG_PATH).unwrap()).unwrap()
+            .set_wallpaper("ferris.png").unwrap()
+    }
+
+    #[test]
+    fn test_command_template() {
+        let setter = CommandSetter::new(vec![
+            String::from("my-setter"),
+            String::from("--file"),
+            String::from("{path}"),
+            String::from("--mode"),
+            String::from("{fit}"),
+            String::from("--name={path}"),
+            String::from("--output={output}"),
+            String::from("{target}")
+        ], FitMode::Center).unwrap();
+        assert_eq!(setter.expand("/tmp/a.jpg", "DP-1", "desktop"), vec!["my-setter", "--file", "/tmp/a.jpg", "--mode", "center", "--name=/tmp/a.jpg", "--output=DP-1", "desktop"]);
+        assert!(CommandSetter::new(Vec::new(), FitMode::Fill).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_command_setter() {
+        let setter = CommandSetter::new(vec![
+            String::from("/bin/sh"),
+            String::from("-c"),
+            String::from("test -f \"$1\""),
+            String::from("sh"),
+            String::from("{path}")
+        ], FitMode::Fill).unwrap();
+        assert!(setter.set_wallpaper("ferris.png").is_ok());
+        assert!(setter.set_wallpaper("missing.png").is_err());
+        assert_eq!(setter.set_lock_screen("ferris.png").unwrap_err().kind(), ErrorKind::Unsupported);
     }
 }
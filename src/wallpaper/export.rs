@@ -0,0 +1,166 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use serde::{Serialize, Deserialize};
+
+use crate::config::ImageFormat;
+use super::{WallpaperSetter, absolute_path};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PhotoInfo {
+    pub id: String,
+    pub source: String,
+    pub attribution: Option<String>,
+    pub path: String,
+    pub query: String,
+    pub output: Option<String>,
+    pub lock_screen: bool
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WallpaperInfo {
+    pub time: String,
+    pub weather: Option<String>,
+    pub photos: Vec<PhotoInfo>
+}
+
+// Publishes the current wallpaper as `current` (plus `current-<output>` and `lock-screen`) and
+// `current.json` in `dir`, optionally on top of a real setter
+pub struct ExportSetter {
+    dir: PathBuf,
+    inner: Option<Box<dyn WallpaperSetter>>
+}
+
+impl ExportSetter {
+    pub fn new(dir: &str, inner: Option<Box<dyn WallpaperSetter>>) -> Result<ExportSetter, io::Error> {
+        fs::create_dir_all(dir)?;
+        Ok(ExportSetter {
+            dir: PathBuf::from(dir),
+            inner
+        })
+    }
+
+    // Everything is written next to its final name first, so readers never see a half-written file
+    fn replace(&self, name: &str, write: impl FnOnce(&Path) -> Result<(), io::Error>) -> Result<(), io::Error> {
+        let temp = self.dir.join(format!(".{}.tmp", name));
+        let _ = fs::remove_file(&temp);
+        write(&temp)?;
+        fs::rename(&temp, self.dir.join(name))
+    }
+
+    fn export(&self, name: &str, path: &str) -> Result<(), io::Error> {
+        let path = absolute_path(path)?;
+        self.replace(name, |temp| {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&path, temp)?;
+            // Symlinks need extra privileges on Windows
+            #[cfg(not(unix))]
+            fs::copy(&path, temp)?;
+            Ok(())
+        })
+    }
+}
+
+impl WallpaperSetter for ExportSetter {
+    fn set_wallpaper(&self, path: &str) -> Result<(), io::Error> {
+        if let Some(inner) = &self.inner {
+            inner.set_wallpaper(path)?;
+        }
+        self.export("current", path)
+    }
+
+    fn set_wallpapers(&self, paths: &HashMap<String, String>) -> Result<(), io::Error> {
+        if let Some(inner) = &self.inner {
+            inner.set_wallpapers(paths)?;
+        }
+        let mut outputs: Vec<(&String, &String)> = paths.iter().collect();
+        outputs.sort();
+        for (output, path) in &outputs {
+            self.export(&format!("current-{}", output), path)?;
+        }
+        match outputs.first() {
+            Some((_, path)) => self.export("current", path),
+            None => Ok(())
+        }
+    }
+
+    fn set_lock_screen(&self, path: &str) -> Result<(), io::Error> {
+        self.export("lock-screen", path)?;
+        match &self.inner {
+            Some(inner) => inner.set_lock_screen(path),
+            None => Ok(())
+        }
+    }
+
+    fn set_info(&self, info: &WallpaperInfo) -> Result<(), io::Error> {
+        if let Some(inner) = &self.inner {
+            inner.set_info(info)?;
+        }
+        let data = serde_json::to_string_pretty(info)?;
+        self.replace("current.json", |temp| fs::write(temp, data))
+    }
+
+    // Exported files are for others to read, so on their own they can be anything
+    fn formats(&self) -> &'static [ImageFormat] {
+        match &self.inner {
+            Some(inner) => inner.formats(),
+            // Whatever can be decoded to check it, so not AVIF
+            None => &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif, ImageFormat::Bmp, ImageFormat::Webp]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn export_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("awc-export-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_export() {
+        let dir = export_dir("single");
+        let setter = ExportSetter::new(&dir, None).unwrap();
+        setter.set_wallpaper("ferris.png").unwrap();
+        setter.set_wallpaper("ferris.png").unwrap();
+        setter.set_info(&WallpaperInfo {
+            time: String::from("2021-05-09T23:00:00+01:00"),
+            weather: Some(String::from("Rain")),
+            photos: vec![PhotoInfo {
+                id: String::from("6GHNuQAVC8Y"),
+                source: String::from("unsplash"),
+                attribution: None,
+                path: String::from("download/6GHNuQAVC8Y.jpg"),
+                query: String::from("late night Rain"),
+                output: None,
+                lock_screen: false
+            }]
+        }).unwrap();
+
+        let current = Path::new(&dir).join("current");
+        assert_eq!(fs::read(&current).unwrap(), fs::read("ferris.png").unwrap());
+        let info: WallpaperInfo = serde_json::from_str(&fs::read_to_string(Path::new(&dir).join("current.json")).unwrap()).unwrap();
+        assert_eq!(info.photos[0].id, "6GHNuQAVC8Y");
+        assert_eq!(info.weather.as_deref(), Some("Rain"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_outputs() {
+        let dir = export_dir("outputs");
+        let setter = ExportSetter::new(&dir, None).unwrap();
+        let mut paths = HashMap::new();
+        paths.insert(String::from("HDMI-1"), String::from("ferris.png"));
+        paths.insert(String::from("DP-1"), String::from("ferris.png"));
+        setter.set_wallpapers(&paths).unwrap();
+        setter.set_lock_screen("ferris.png").unwrap();
+        for name in &["current", "current-DP-1", "current-HDMI-1", "lock-screen"] {
+            assert!(Path::new(&dir).join(name).exists(), "{} is missing", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
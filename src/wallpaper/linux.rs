@@ -0,0 +1,593 @@
+use std::{collections::HashMap, env, io::{self, ErrorKind}, iter, path::PathBuf, process::Command};
+
+use crate::{Config, config::{Desktop, FitMode, ImageFormat}};
+use super::{WallpaperSetter, absolute_path, outputs::list_outputs, wayland};
+
+pub struct LinuxSetter {
+    desktop: Desktop,
+    fit: FitMode
+}
+
+pub fn make_setter(config: &Config) -> Result<Box<dyn WallpaperSetter>, io::Error> {
+    let desktop = match &config.desktop {
+        Some(desktop) => desktop.clone(),
+        None => detect_desktop().ok_or_else(|| io::Error::new(ErrorKind::NotFound,
+            "Could not detect the desktop environment, please set `desktop` in config.json"))?
+    };
+    Ok(Box::new(LinuxSetter {
+        desktop,
+        fit: config.fit.clone()
+    }))
+}
+
+pub fn detect_desktop() -> Option<Desktop> {
+    let current_desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    let session = env::var("DESKTOP_SESSION").unwrap_or_default();
+    if let Some(desktop) = desktop_from_env(&current_desktop, &session) {
+        return Some(desktop);
+    }
+    if let Some(desktop) = wayland::detect_compositor() {
+        return Some(desktop);
+    }
+    // Standalone window managers don't announce themselves, so use whatever setter is installed
+    [Desktop::Feh, Desktop::Nitrogen, Desktop::Xwallpaper].iter()
+        .find(|desktop| find_program(program_for(desktop)).is_some())
+        .cloned()
+}
+
+fn desktop_from_env(current_desktop: &str, session: &str) -> Option<Desktop> {
+    // XDG_CURRENT_DESKTOP is a colon separated list, e.g. "ubuntu:GNOME"
+    for name in current_desktop.split(':').chain(iter::once(session)) {
+        let desktop = match name.to_lowercase().as_str() {
+            "gnome" | "gnome-xorg" | "gnome-classic" | "ubuntu" | "unity" | "budgie" | "pantheon" => Desktop::Gnome,
+            "kde" | "plasma" | "plasmawayland" => Desktop::Kde,
+            "xfce" | "xfce4" | "xubuntu" => Desktop::Xfce,
+            "mate" => Desktop::Mate,
+            "cinnamon" | "x-cinnamon" => Desktop::Cinnamon,
+            _ => continue
+        };
+        return Some(desktop);
+    }
+    None
+}
+
+fn program_for(desktop: &Desktop) -> &'static str {
+    match desktop {
+        Desktop::Gnome | Desktop::Mate | Desktop::Cinnamon => "gsettings",
+        Desktop::Kde => "qdbus",
+        Desktop::Xfce => "xfconf-query",
+        Desktop::Feh => "feh",
+        Desktop::Nitrogen => "nitrogen",
+        Desktop::Xwallpaper => "xwallpaper",
+        Desktop::Sway => "swaymsg",
+        Desktop::Swaybg => "swaybg",
+        Desktop::Swww => "swww",
+        Desktop::Hyprpaper => "hyprctl"
+    }
+}
+
+impl WallpaperSetter for LinuxSetter {
+    fn set_wallpaper(&self, path: &str) -> Result<(), io::Error> {
+        let path = absolute_path(path)?;
+        let uri = format!("file://{}", path);
+        match self.desktop {
+            Desktop::Gnome => {
+                self.set_gsettings("org.gnome.desktop.background")?;
+                run("gsettings", &["set", "org.gnome.desktop.background", "picture-uri", &uri])?;
+                // `picture-uri-dark` only exists since GNOME 42, so older versions are allowed to refuse it
+                if let Err(e) = run("gsettings", &["set", "org.gnome.desktop.background", "picture-uri-dark", &uri]) {
+                    eprintln!("Could not set dark mode wallpaper: {}. Skipping...", e);
+                }
+            },
+            Desktop::Kde => {
+                self.set_plasma_images(&[uri])?;
+            },
+            Desktop::Xfce => {
+                let properties = run("xfconf-query", &["-c", "xfce4-desktop", "-l"])?;
+                let properties: Vec<&str> = properties.lines()
+                    .filter(|property| property.ends_with("/last-image"))
+                    .collect();
+                if properties.is_empty() {
+                    return Err(io::Error::new(ErrorKind::NotFound, "xfce4-desktop has no backdrop to set"));
+                }
+                for property in properties {
+                    self.set_xfce_backdrop(property, &path)?;
+                }
+            },
+            Desktop::Mate => {
+                self.set_gsettings("org.mate.background")?;
+                run("gsettings", &["set", "org.mate.background", "picture-filename", &path])?;
+            },
+            Desktop::Cinnamon => {
+                self.set_gsettings("org.cinnamon.desktop.background")?;
+                run("gsettings", &["set", "org.cinnamon.desktop.background", "picture-uri", &uri])?;
+            },
+            Desktop::Feh => {
+                let mut args = self.feh_args();
+                args.push(&path);
+                run("feh", &args)?;
+            },
+            Desktop::Nitrogen => {
+                run("nitrogen", &[self.nitrogen_mode(), "--save", &path])?;
+            },
+            Desktop::Xwallpaper => {
+                run("xwallpaper", &[self.xwallpaper_mode(), &path])?;
+            },
+            Desktop::Sway | Desktop::Swaybg | Desktop::Swww | Desktop::Hyprpaper => {
+                wayland::set_wallpaper_on(&self.desktop, &self.fit, &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_wallpapers(&self, paths: &HashMap<String, String>) -> Result<(), io::Error> {
+        // (output name, absolute path) pairs
+        let mut outputs = Vec::new();
+        for (output, path) in paths {
+            outputs.push((output.clone(), absolute_path(path)?));
+        }
+        outputs.sort();
+        match self.desktop {
+            Desktop::Feh | Desktop::Nitrogen => {
+                // Both of them count screens instead of naming them
+                let order: Vec<String> = list_outputs()?.into_iter().map(|output| output.name).collect();
+                outputs.sort_by_key(|(name, _)| order.iter().position(|other| other == name));
+                if self.desktop == Desktop::Feh {
+                    let mut args = self.feh_args();
+                    args.extend(outputs.iter().map(|(_, path)| path.as_str()));
+                    run("feh", &args)?;
+                } else {
+                    for (head, (_, path)) in outputs.iter().enumerate() {
+                        run("nitrogen", &[&format!("--head={}", head), self.nitrogen_mode(), "--save", path])?;
+                    }
+                }
+            },
+            Desktop::Xwallpaper => {
+                let mut args = Vec::new();
+                for (name, path) in &outputs {
+                    args.extend(&["--output", name.as_str(), self.xwallpaper_mode(), path.as_str()]);
+                }
+                run("xwallpaper", &args)?;
+            },
+            Desktop::Xfce => {
+                // Backdrop properties look like /backdrop/screen0/monitorHDMI-1/workspace0/last-image
+                let properties = run("xfconf-query", &["-c", "xfce4-desktop", "-l"])?;
+                for property in properties.lines().filter(|property| property.ends_with("/last-image")) {
+                    let output = outputs.iter()
+                        .find(|(name, _)| property.contains(&format!("/monitor{}/", name)));
+                    if let Some((_, path)) = output {
+                        self.set_xfce_backdrop(property, path)?;
+                    }
+                }
+            },
+            Desktop::Kde => {
+                // Plasma numbers screens in the order they're listed
+                let order: Vec<String> = list_outputs()?.into_iter().map(|output| output.name).collect();
+                outputs.sort_by_key(|(name, _)| order.iter().position(|other| other == name));
+                let uris: Vec<String> = outputs.iter().map(|(_, path)| format!("file://{}", path)).collect();
+                self.set_plasma_images(&uris)?;
+            },
+            Desktop::Sway | Desktop::Swaybg | Desktop::Swww | Desktop::Hyprpaper => {
+                wayland::set_wallpapers_on(&self.desktop, &self.fit, &outputs)?;
+            },
+            // These can only show one picture across all monitors
+            Desktop::Gnome | Desktop::Mate | Desktop::Cinnamon => {
+                if let Some((name, path)) = outputs.first() {
+                    eprintln!("{:?} can't show a picture per monitor, using the one for {} on all of them...", self.desktop, name);
+                    self.set_wallpaper(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_lock_screen(&self, path: &str) -> Result<(), io::Error> {
+        let path = absolute_path(path)?;
+        let uri = format!("file://{}", path);
+        match self.desktop {
+            Desktop::Gnome => {
+                self.set_gsettings("org.gnome.desktop.screensaver")?;
+                run("gsettings", &["set", "org.gnome.desktop.screensaver", "picture-uri", &uri])?;
+            },
+            Desktop::Kde => {
+                let kwriteconfig = ["kwriteconfig6", "kwriteconfig5"].iter()
+                    .find(|program| find_program(program).is_some())
+                    .unwrap_or(&"kwriteconfig5");
+                run(kwriteconfig, &["--file", "kscreenlockerrc", "--group", "Greeter",
+                    "--key", "WallpaperPlugin", "org.kde.image"])?;
+                run(kwriteconfig, &["--file", "kscreenlockerrc", "--group", "Greeter", "--group", "Wallpaper",
+                    "--group", "org.kde.image", "--group", "General", "--key", "Image", &uri])?;
+            },
+            // swaylock is the locker of choice on wlroots compositors
+            Desktop::Sway | Desktop::Swaybg | Desktop::Swww | Desktop::Hyprpaper => {
+                wayland::set_swaylock_image(&path)?;
+            },
+            Desktop::Xfce | Desktop::Mate | Desktop::Cinnamon | Desktop::Feh | Desktop::Nitrogen | Desktop::Xwallpaper => {
+                return Err(io::Error::new(ErrorKind::Unsupported,
+                    format!("Can't change the lock screen on {:?}", self.desktop)));
+            }
+        }
+        Ok(())
+    }
+
+    fn formats(&self) -> &'static [ImageFormat] {
+        match self.desktop {
+            // Qt's image plugins read WebP, on the desktop and the lock screen alike
+            Desktop::Kde => &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif, ImageFormat::Bmp, ImageFormat::Webp],
+            // gdk-pixbuf needs an extra loader for WebP
+            Desktop::Gnome | Desktop::Mate | Desktop::Cinnamon | Desktop::Xfce | Desktop::Sway | Desktop::Swaybg =>
+                &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif, ImageFormat::Bmp],
+            // swww and hyprpaper read WebP, but swaylock may be handed the same photo
+            Desktop::Feh | Desktop::Nitrogen | Desktop::Xwallpaper | Desktop::Swww | Desktop::Hyprpaper =>
+                &[ImageFormat::Jpeg, ImageFormat::Png]
+        }
+    }
+}
+
+impl LinuxSetter {
+    // GNOME, MATE and Cinnamon share the same `picture-options` values
+    fn set_gsettings(&self, schema: &str) -> Result<(), io::Error> {
+        let options = match self.fit {
+            FitMode::Fill => "zoom",
+            FitMode::Fit => "scaled",
+            FitMode::Center => "centered",
+            FitMode::Tile => "wallpaper",
+            FitMode::Stretch => "stretched",
+            FitMode::Span => "spanned"
+        };
+        run("gsettings", &["set", schema, "picture-options", options])?;
+        Ok(())
+    }
+
+    // Desktops get the image of their screen, or the first one when there are fewer images than screens
+    fn set_plasma_images(&self, uris: &[String]) -> Result<(), io::Error> {
+        // See FillMode in plasma-workspace's image wallpaper plugin
+        let fill_mode = match self.fit {
+            FitMode::Stretch => 0,
+            FitMode::Fit => 1,
+            FitMode::Fill | FitMode::Span => 2,
+            FitMode::Tile => 3,
+            FitMode::Center => 6
+        };
+        let script = format!("\
+var images = {};
+var allDesktops = desktops();
+for (var i = 0; i < allDesktops.length; i++) {{
+    var d = allDesktops[i];
+    d.wallpaperPlugin = \"org.kde.image\";
+    d.currentConfigGroup = Array(\"Wallpaper\", \"org.kde.image\", \"General\");
+    d.writeConfig(\"Image\", images[d.screen] || images[0]);
+    d.writeConfig(\"FillMode\", {});
+}}", serde_json::to_string(uris)?, fill_mode);
+        // Plasma 6 ships qdbus under a different name
+        let qdbus = ["qdbus", "qdbus6", "qdbus-qt6", "qdbus-qt5"].iter()
+            .find(|program| find_program(program).is_some())
+            .unwrap_or(&"qdbus");
+        run(qdbus, &["org.kde.plasmashell", "/PlasmaShell", "org.kde.PlasmaShell.evaluateScript", &script])?;
+        Ok(())
+    }
+
+    fn set_xfce_backdrop(&self, property: &str, path: &str) -> Result<(), io::Error> {
+        let style = match self.fit {
+            FitMode::Center => "1",
+            FitMode::Tile => "2",
+            FitMode::Stretch => "3",
+            FitMode::Fit => "4",
+            FitMode::Fill => "5",
+            FitMode::Span => "6"
+        };
+        let style_property = property.replace("/last-image", "/image-style");
+        run("xfconf-query", &["-c", "xfce4-desktop", "-p", &style_property, "-n", "-t", "int", "-s", style])?;
+        run("xfconf-query", &["-c", "xfce4-desktop", "-p", property, "-s", path])?;
+        Ok(())
+    }
+
+    fn feh_args(&self) -> Vec<&str> {
+        match self.fit {
+            FitMode::Fill => vec!["--bg-fill"],
+            FitMode::Fit => vec!["--bg-max"],
+            FitMode::Center => vec!["--bg-center"],
+            FitMode::Tile => vec!["--bg-tile"],
+            FitMode::Stretch => vec!["--bg-scale"],
+            FitMode::Span => vec!["--no-xinerama", "--bg-fill"]
+        }
+    }
+
+    fn nitrogen_mode(&self) -> &'static str {
+        match self.fit {
+            FitMode::Fill | FitMode::Span => "--set-zoom-fill",
+            FitMode::Fit => "--set-zoom",
+            FitMode::Center => "--set-centered",
+            FitMode::Tile => "--set-tiled",
+            FitMode::Stretch => "--set-scaled"
+        }
+    }
+
+    fn xwallpaper_mode(&self) -> &'static str {
+        match self.fit {
+            FitMode::Fill | FitMode::Span => "--zoom",
+            FitMode::Fit => "--maximize",
+            FitMode::Center => "--center",
+            FitMode::Tile => "--tile",
+            FitMode::Stretch => "--stretch"
+        }
+    }
+}
+
+pub(crate) fn find_program(program: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+pub(crate) fn run(program: &str, args: &[&str]) -> Result<String, io::Error> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{} failed with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod stubs {
+    use std::{cell::RefCell, env, ffi::{OsStr, OsString}, fs, os::unix::fs::PermissionsExt, path::PathBuf, process, sync::MutexGuard};
+
+    use super::super::ENV_LOCK;
+
+    // Replaces PATH with a directory of fake executables that log their arguments. Tests change other
+    // environment variables through it too, so they're all put back once it's dropped
+    pub struct Stubs {
+        pub dir: PathBuf,
+        old_vars: RefCell<Vec<(String, Option<OsString>)>>,
+        _lock: MutexGuard<'static, ()>
+    }
+
+    impl Stubs {
+        pub fn new(name: &str) -> Stubs {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let dir = env::temp_dir().join(format!("awc-stubs-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let stubs = Stubs {
+                dir,
+                old_vars: RefCell::new(Vec::new()),
+                _lock: lock
+            };
+            stubs.set_var("PATH", &stubs.dir);
+            stubs
+        }
+
+        pub fn set_var(&self, name: &str, value: impl AsRef<OsStr>) {
+            self.save_var(name);
+            env::set_var(name, value);
+        }
+
+        pub fn remove_var(&self, name: &str) {
+            self.save_var(name);
+            env::remove_var(name);
+        }
+
+        // Only the value from before the first change is worth restoring
+        fn save_var(&self, name: &str) {
+            let mut old_vars = self.old_vars.borrow_mut();
+            if !old_vars.iter().any(|(saved, _)| saved == name) {
+                old_vars.push((String::from(name), env::var_os(name)));
+            }
+        }
+
+        pub fn add(&self, program: &str, stdout: &str) {
+            self.add_script(program, &format!("printf '%s' '{}'", stdout));
+        }
+
+        pub fn add_script(&self, program: &str, script: &str) {
+            let path = self.dir.join(program);
+            fs::write(&path, format!("#!/bin/sh\necho \"{} $*\" >> \"{}\"\n{}\n",
+                program,
+                self.dir.join("calls.log").display(),
+                script)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        pub fn calls(&self) -> Vec<String> {
+            fs::read_to_string(self.dir.join("calls.log"))
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    impl Drop for Stubs {
+        fn drop(&mut self) {
+            for (name, value) in self.old_vars.borrow().iter().rev() {
+                match value {
+                    Some(value) => env::set_var(name, value),
+                    None => env::remove_var(name)
+                }
+            }
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::stubs::Stubs;
+
+    fn setter(desktop: Desktop) -> LinuxSetter {
+        LinuxSetter {
+            desktop,
+            fit: FitMode::Fill
+        }
+    }
+
+    #[test]
+    fn test_desktop_from_env() {
+        assert_eq!(desktop_from_env("ubuntu:GNOME", "ubuntu"), Some(Desktop::Gnome));
+        assert_eq!(desktop_from_env("KDE", "plasma"), Some(Desktop::Kde));
+        assert_eq!(desktop_from_env("XFCE", ""), Some(Desktop::Xfce));
+        assert_eq!(desktop_from_env("X-Cinnamon", "cinnamon"), Some(Desktop::Cinnamon));
+        assert_eq!(desktop_from_env("", "mate"), Some(Desktop::Mate));
+        assert_eq!(desktop_from_env("", "i3"), None);
+    }
+
+    #[test]
+    fn test_detect_standalone_setter() {
+        let stubs = Stubs::new("detect");
+        stubs.add("nitrogen", "");
+        stubs.remove_var("XDG_CURRENT_DESKTOP");
+        stubs.remove_var("DESKTOP_SESSION");
+        stubs.remove_var("WAYLAND_DISPLAY");
+        assert_eq!(detect_desktop(), Some(Desktop::Nitrogen));
+    }
+
+    #[test]
+    fn test_gnome() {
+        let stubs = Stubs::new("gnome");
+        stubs.add("gsettings", "");
+        setter(Desktop::Gnome).set_wallpaper("ferris.png").unwrap();
+        let calls = stubs.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0], "gsettings set org.gnome.desktop.background picture-options zoom");
+        assert!(calls[1].starts_with("gsettings set org.gnome.desktop.background picture-uri file:///"));
+        assert!(calls[1].ends_with("/ferris.png"));
+        assert!(calls[2].contains("picture-uri-dark"));
+    }
+
+    #[test]
+    fn test_kde() {
+        let stubs = Stubs::new("kde");
+        stubs.add("qdbus", "");
+        setter(Desktop::Kde).set_wallpaper("ferris.png").unwrap();
+        let calls = stubs.calls().join("\n");
+        assert!(calls.starts_with("qdbus org.kde.plasmashell /PlasmaShell org.kde.PlasmaShell.evaluateScript"));
+        assert!(calls.contains("ferris.png"));
+        assert!(calls.contains("d.writeConfig(\"FillMode\", 2);"));
+    }
+
+    #[test]
+    fn test_kde_per_output() {
+        let stubs = Stubs::new("kde-per-output");
+        stubs.add("xrandr", "HDMI-1 connected 1920x1080+1080+0\nDP-1 connected 1080x1920+0+0 left\n");
+        stubs.add("qdbus", "");
+        let mut paths = HashMap::new();
+        paths.insert(String::from("HDMI-1"), String::from("/tmp/a.jpg"));
+        paths.insert(String::from("DP-1"), String::from("/tmp/b.jpg"));
+        setter(Desktop::Kde).set_wallpapers(&paths).unwrap();
+        let calls = stubs.calls().join("\n");
+        assert!(calls.contains("var images = [\"file:///tmp/b.jpg\",\"file:///tmp/a.jpg\"];"));
+        assert!(calls.contains("d.writeConfig(\"Image\", images[d.screen] || images[0]);"));
+    }
+
+    #[test]
+    fn test_xfce() {
+        let stubs = Stubs::new("xfce");
+        stubs.add("xfconf-query", "/backdrop/screen0/monitorHDMI-1/workspace0/last-image\n/backdrop/screen0/monitorHDMI-1/workspace0/image-style\n/backdrop/screen0/monitorDP-1/workspace0/last-image\n");
+        setter(Desktop::Xfce).set_wallpaper("ferris.png").unwrap();
+        let calls = stubs.calls();
+        assert_eq!(calls.len(), 5);
+        assert!(calls[1].contains("-p /backdrop/screen0/monitorHDMI-1/workspace0/image-style -n -t int -s 5"));
+        assert!(calls[2].contains("-p /backdrop/screen0/monitorHDMI-1/workspace0/last-image -s /"));
+        assert!(calls[4].contains("-p /backdrop/screen0/monitorDP-1/workspace0/last-image -s /"));
+    }
+
+    #[test]
+    fn test_mate_and_cinnamon() {
+        let stubs = Stubs::new("mate");
+        stubs.add("gsettings", "");
+        setter(Desktop::Mate).set_wallpaper("ferris.png").unwrap();
+        setter(Desktop::Cinnamon).set_wallpaper("ferris.png").unwrap();
+        let calls = stubs.calls();
+        assert!(calls[1].starts_with("gsettings set org.mate.background picture-filename /"));
+        assert!(calls[3].starts_with("gsettings set org.cinnamon.desktop.background picture-uri file:///"));
+    }
+
+    #[test]
+    fn test_x11_setters() {
+        let stubs = Stubs::new("x11");
+        stubs.add("feh", "");
+        stubs.add("nitrogen", "");
+        stubs.add("xwallpaper", "");
+        setter(Desktop::Feh).set_wallpaper("ferris.png").unwrap();
+        setter(Desktop::Nitrogen).set_wallpaper("ferris.png").unwrap();
+        setter(Desktop::Xwallpaper).set_wallpaper("ferris.png").unwrap();
+        let calls = stubs.calls();
+        assert!(calls[0].starts_with("feh --bg-fill /"));
+        assert!(calls[1].starts_with("nitrogen --set-zoom-fill --save /"));
+        assert!(calls[2].starts_with("xwallpaper --zoom /"));
+    }
+
+    #[test]
+    fn test_fit_modes() {
+        let stubs = Stubs::new("fit");
+        stubs.add("feh", "");
+        stubs.add("gsettings", "");
+        let setter = LinuxSetter {
+            desktop: Desktop::Feh,
+            fit: FitMode::Fit
+        };
+        setter.set_wallpaper("/tmp/a.jpg").unwrap();
+        let setter = LinuxSetter {
+            desktop: Desktop::Mate,
+            fit: FitMode::Tile
+        };
+        setter.set_wallpaper("/tmp/a.jpg").unwrap();
+        assert_eq!(stubs.calls(), vec![
+            "feh --bg-max /tmp/a.jpg",
+            "gsettings set org.mate.background picture-options wallpaper",
+            "gsettings set org.mate.background picture-filename /tmp/a.jpg"
+        ]);
+    }
+
+    #[test]
+    fn test_per_output() {
+        let stubs = Stubs::new("per-output");
+        stubs.add("xrandr", "HDMI-1 connected 1920x1080+1080+0\nDP-1 connected 1080x1920+0+0 left\n");
+        stubs.add("feh", "");
+        stubs.add("xwallpaper", "");
+        stubs.add("xfconf-query", "/backdrop/screen0/monitorHDMI-1/workspace0/last-image\n/backdrop/screen0/monitorDP-1/workspace0/last-image\n");
+        let mut paths = HashMap::new();
+        paths.insert(String::from("HDMI-1"), String::from("/tmp/a.jpg"));
+        paths.insert(String::from("DP-1"), String::from("/tmp/b.jpg"));
+        setter(Desktop::Feh).set_wallpapers(&paths).unwrap();
+        setter(Desktop::Xwallpaper).set_wallpapers(&paths).unwrap();
+        setter(Desktop::Xfce).set_wallpapers(&paths).unwrap();
+        assert_eq!(stubs.calls(), vec![
+            "xrandr --query",
+            "feh --bg-fill /tmp/b.jpg /tmp/a.jpg",
+            "xwallpaper --output DP-1 --zoom /tmp/b.jpg --output HDMI-1 --zoom /tmp/a.jpg",
+            "xfconf-query -c xfce4-desktop -l",
+            "xfconf-query -c xfce4-desktop -p /backdrop/screen0/monitorHDMI-1/workspace0/image-style -n -t int -s 5",
+            "xfconf-query -c xfce4-desktop -p /backdrop/screen0/monitorHDMI-1/workspace0/last-image -s /tmp/a.jpg",
+            "xfconf-query -c xfce4-desktop -p /backdrop/screen0/monitorDP-1/workspace0/image-style -n -t int -s 5",
+            "xfconf-query -c xfce4-desktop -p /backdrop/screen0/monitorDP-1/workspace0/last-image -s /tmp/b.jpg"
+        ]);
+    }
+
+    #[test]
+    fn test_lock_screen() {
+        let stubs = Stubs::new("lock-screen");
+        stubs.add("gsettings", "");
+        stubs.add("kwriteconfig5", "");
+        setter(Desktop::Gnome).set_lock_screen("/tmp/a.jpg").unwrap();
+        setter(Desktop::Kde).set_lock_screen("/tmp/a.jpg").unwrap();
+        assert_eq!(stubs.calls(), vec![
+            "gsettings set org.gnome.desktop.screensaver picture-options zoom",
+            "gsettings set org.gnome.desktop.screensaver picture-uri file:///tmp/a.jpg",
+            "kwriteconfig5 --file kscreenlockerrc --group Greeter --key WallpaperPlugin org.kde.image",
+            "kwriteconfig5 --file kscreenlockerrc --group Greeter --group Wallpaper --group org.kde.image --group General --key Image file:///tmp/a.jpg"
+        ]);
+        assert_eq!(setter(Desktop::Feh).set_lock_screen("/tmp/a.jpg").unwrap_err().kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_missing_program() {
+        let _stubs = Stubs::new("missing");
+        assert!(setter(Desktop::Feh).set_wallpaper("ferris.png").is_err());
+    }
+}
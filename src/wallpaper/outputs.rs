@@ -0,0 +1,284 @@
+use std::{env, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use super::Output;
+use super::linux::{find_program, run};
+
+pub fn list_outputs() -> Result<Vec<Output>, io::Error> {
+    if env::var_os("SWAYSOCK").is_some() {
+        return parse_sway(&run("swaymsg", &["-t", "get_outputs", "-r"])?);
+    }
+    if env::var_os("WAYLAND_DISPLAY").is_some() && find_program("wlr-randr").is_some() {
+        return Ok(parse_wlr_randr(&run("wlr-randr", &[])?));
+    }
+    // Without X there's still the kernel's view of connected monitors
+    match run("xrandr", &["--query"]).map(|data| parse_xrandr(&data)) {
+        Ok(outputs) if !outputs.is_empty() => Ok(outputs),
+        Ok(outputs) => Ok(parse_drm(Path::new(DRM_PATH)).unwrap_or(outputs)),
+        Err(e) => parse_drm(Path::new(DRM_PATH)).ok_or(e)
+    }
+}
+
+const DRM_PATH: &str = "/sys/class/drm";
+
+// Connectors look like /sys/class/drm/card0-HDMI-A-1, with their preferred mode first in `modes`.
+// There's no telling where monitors are from here, so they're in connector order
+fn parse_drm(root: &Path) -> Option<Vec<Output>> {
+    let mut outputs = Vec::new();
+    for entry in fs::read_dir(root).ok()?.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let connector = match name.split_once('-') {
+            Some((card, connector)) if card.starts_with("card") => connector.to_string(),
+            _ => continue
+        };
+        let status = fs::read_to_string(entry.path().join("status")).unwrap_or_default();
+        if status.trim() != "connected" {
+            continue;
+        }
+        let modes = fs::read_to_string(entry.path().join("modes")).unwrap_or_default();
+        let mode = modes.lines().next().and_then(|mode| mode.split_once('x'));
+        if let Some((width, height)) = mode {
+            // Interlaced modes end in "i", e.g. "1920x1080i"
+            if let (Ok(width), Ok(height)) = (width.parse(), height.trim_end_matches('i').parse()) {
+                outputs.push(Output {
+                    name: connector,
+                    width,
+                    height,
+                    scale: 1.0
+                });
+            }
+        }
+    }
+    outputs.sort_by(|a, b| a.name.cmp(&b.name));
+    Some(outputs)
+}
+
+// Outputs are ordered left to right, top to bottom, which is also how feh and nitrogen count screens
+fn sorted(mut outputs: Vec<(i32, i32, Output)>) -> Vec<Output> {
+    outputs.sort_by_key(|(x, y, _)| (*x, *y));
+    outputs.into_iter().map(|(_, _, output)| output).collect()
+}
+
+// e.g. "DP-1 connected 1080x1920+1920+0 left (normal left inverted right x axis y axis) 527mm x 296mm"
+fn parse_xrandr(data: &str) -> Vec<Output> {
+    let mut outputs = Vec::new();
+    for line in data.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < 3 || tokens[1] != "connected" {
+            continue;
+        }
+        // Connected but disabled outputs have no geometry
+        let geometry = tokens[2..].iter().find_map(|token| parse_geometry(token));
+        if let Some((width, height, x, y)) = geometry {
+            outputs.push((x, y, Output {
+                name: String::from(tokens[0]),
+                width,
+                height,
+                scale: 1.0
+            }));
+        }
+    }
+    sorted(outputs)
+}
+
+fn parse_geometry(token: &str) -> Option<(u32, u32, i32, i32)> {
+    let (size, position) = token.split_once('+')?;
+    let (width, height) = size.split_once('x')?;
+    let (x, y) = position.split_once('+')?;
+    Some((width.parse().ok()?, height.parse().ok()?, x.parse().ok()?, y.parse().ok()?))
+}
+
+#[derive(Default)]
+struct WlrOutput {
+    name: String,
+    enabled: bool,
+    mode: Option<(u32, u32)>,
+    position: (i32, i32),
+    rotated: bool,
+    scale: Option<f32>
+}
+
+impl WlrOutput {
+    fn finish(self) -> Option<(i32, i32, Output)> {
+        let (width, height) = self.mode?;
+        if !self.enabled {
+            return None;
+        }
+        let (width, height) = if self.rotated { (height, width) } else { (width, height) };
+        // Modes are in physical pixels
+        let scale = self.scale.filter(|scale| *scale > 0.0).unwrap_or(1.0);
+        Some((self.position.0, self.position.1, Output {
+            name: self.name,
+            width: (width as f32 / scale).round() as u32,
+            height: (height as f32 / scale).round() as u32,
+            scale
+        }))
+    }
+}
+
+fn parse_wlr_randr(data: &str) -> Vec<Output> {
+    let mut outputs = Vec::new();
+    let mut current: Option<WlrOutput> = None;
+    for line in data.lines() {
+        if !line.starts_with(' ') {
+            outputs.extend(current.take().and_then(WlrOutput::finish));
+            current = line.split_whitespace().next().map(|name| WlrOutput {
+                name: String::from(name),
+                enabled: true,
+                ..Default::default()
+            });
+            continue;
+        }
+        let current = match current.as_mut() {
+            Some(current) => current,
+            None => continue
+        };
+        let line = line.trim();
+        if let Some(enabled) = line.strip_prefix("Enabled:") {
+            current.enabled = enabled.trim() == "yes";
+        } else if let Some(position) = line.strip_prefix("Position:") {
+            if let Some((x, y)) = position.trim().split_once(',') {
+                current.position = (x.parse().unwrap_or(0), y.parse().unwrap_or(0));
+            }
+        } else if let Some(transform) = line.strip_prefix("Transform:") {
+            let transform = transform.trim();
+            current.rotated = transform.ends_with("90") || transform.ends_with("270");
+        } else if let Some(scale) = line.strip_prefix("Scale:") {
+            current.scale = scale.trim().parse().ok();
+        } else if line.contains("current") {
+            // e.g. "1920x1080 px, 60.000000 Hz (preferred, current)"
+            let mode = line.split_whitespace().next().and_then(|mode| mode.split_once('x'));
+            if let Some((width, height)) = mode {
+                if let (Ok(width), Ok(height)) = (width.parse(), height.parse()) {
+                    current.mode = Some((width, height));
+                }
+            }
+        }
+    }
+    outputs.extend(current.and_then(WlrOutput::finish));
+    sorted(outputs)
+}
+
+#[derive(Deserialize)]
+struct SwayRect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32
+}
+
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    active: bool,
+    rect: SwayRect,
+    // Missing or -1 on disabled outputs
+    #[serde(default)]
+    scale: Option<f32>
+}
+
+fn parse_sway(data: &str) -> Result<Vec<Output>, io::Error> {
+    let outputs: Vec<SwayOutput> = serde_json::from_str(data)?;
+    Ok(sorted(outputs.into_iter()
+        .filter(|output| output.active)
+        .map(|output| (output.rect.x, output.rect.y, Output {
+            name: output.name,
+            width: output.rect.width,
+            height: output.rect.height,
+            scale: output.scale.filter(|scale| *scale > 0.0).unwrap_or(1.0)
+        }))
+        .collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Orientation;
+    use super::*;
+
+    fn output(name: &str, width: u32, height: u32) -> Output {
+        scaled(name, width, height, 1.0)
+    }
+
+    fn scaled(name: &str, width: u32, height: u32, scale: f32) -> Output {
+        Output {
+            name: String::from(name),
+            width,
+            height,
+            scale
+        }
+    }
+
+    #[test]
+    fn test_xrandr() {
+        let data = "\
+Screen 0: minimum 320 x 200, current 3000 x 1920, maximum 16384 x 16384
+DP-1 connected 1080x1920+1920+0 left (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00*+
+HDMI-1 connected primary 1920x1080+0+0 (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00*+
+DP-2 disconnected (normal left inverted right x axis y axis)
+HDMI-2 connected (normal left inverted right x axis y axis)
+";
+        let outputs = parse_xrandr(data);
+        assert_eq!(outputs, vec![output("HDMI-1", 1920, 1080), output("DP-1", 1080, 1920)]);
+        assert_eq!(outputs[1].orientation(), Orientation::Portrait);
+    }
+
+    #[test]
+    fn test_drm() {
+        let root = env::temp_dir().join(format!("awc-drm-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, status, modes) in &[
+            ("card0-eDP-1", "connected\n", "2880x1800\n1920x1200\n"),
+            ("card0-DP-1", "connected\n", "1920x1080i\n"),
+            ("card0-HDMI-A-1", "disconnected\n", ""),
+            ("card1-DP-2", "connected\n", "")
+        ] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("status"), status).unwrap();
+            fs::write(root.join(name).join("modes"), modes).unwrap();
+        }
+        fs::create_dir_all(root.join("card0")).unwrap();
+        fs::write(root.join("version"), "drm 1.1.0 20060810\n").unwrap();
+        assert_eq!(parse_drm(&root), Some(vec![output("DP-1", 1920, 1080), output("eDP-1", 2880, 1800)]));
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(parse_drm(&root), None);
+    }
+
+    #[test]
+    fn test_wlr_randr() {
+        let data = "\
+DP-1 \"Dell Inc. DELL U2719D (DP-1)\"
+  Enabled: yes
+  Modes:
+    2560x1440 px, 59.951000 Hz (preferred, current)
+    1920x1080 px, 60.000000 Hz
+  Position: 2560,0
+  Transform: 270
+  Scale: 1.000000
+HDMI-A-1 \"Goldstar Company Ltd LG HDR 4K (HDMI-A-1)\"
+  Enabled: yes
+  Modes:
+    3840x2160 px, 60.000000 Hz (preferred, current)
+  Position: 0,0
+  Transform: normal
+  Scale: 1.500000
+HDMI-A-2 \"Unknown\"
+  Enabled: no
+  Modes:
+    1920x1080 px, 60.000000 Hz (preferred)
+";
+        assert_eq!(parse_wlr_randr(data), vec![scaled("HDMI-A-1", 2560, 1440, 1.5), output("DP-1", 1440, 2560)]);
+    }
+
+    #[test]
+    fn test_sway() {
+        let data = r#"[
+            {"name": "eDP-1", "active": true, "scale": 2.0, "rect": {"x": 1920, "y": 0, "width": 1280, "height": 800}},
+            {"name": "DP-3", "active": true, "scale": 1.0, "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+            {"name": "DP-4", "active": false, "rect": {"x": 0, "y": 0, "width": 0, "height": 0}}
+        ]"#;
+        assert_eq!(parse_sway(data).unwrap(), vec![output("DP-3", 1920, 1080), scaled("eDP-1", 1280, 800, 2.0)]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{env, fs, io::{self, ErrorKind}, path::PathBuf, process::{Child, Command, Stdio}, sync::Mutex};
+
+use crate::config::{Desktop, FitMode};
+use super::linux::{find_program, run};
+
+// The swaybg instance we started last, which has to be replaced whenever the wallpaper changes
+static SWAYBG: Mutex<Option<Child>> = Mutex::new(None);
+
+pub fn detect_compositor() -> Option<Desktop> {
+    env::var_os("WAYLAND_DISPLAY")?;
+    if env::var_os("SWAYSOCK").is_some() {
+        return Some(Desktop::Sway);
+    }
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() && find_program("hyprpaper").is_some() {
+        return Some(Desktop::Hyprpaper);
+    }
+    // river and other wlroots compositors have no wallpaper support of their own
+    if find_program("swww").is_some() {
+        return Some(Desktop::Swww);
+    }
+    if find_program("swaybg").is_some() {
+        return Some(Desktop::Swaybg);
+    }
+    None
+}
+
+// swaybg and sway's `output bg` take the same modes
+fn swaybg_mode(fit: &FitMode) -> &'static str {
+    match fit {
+        FitMode::Fill | FitMode::Span => "fill",
+        FitMode::Fit => "fit",
+        FitMode::Center => "center",
+        FitMode::Tile => "tile",
+        FitMode::Stretch => "stretch"
+    }
+}
+
+fn swww_resize(fit: &FitMode) -> &'static str {
+    match fit {
+        FitMode::Fit => "fit",
+        FitMode::Center => "no",
+        _ => "crop"
+    }
+}
+
+fn hyprpaper_path(fit: &FitMode, path: &str) -> String {
+    match fit {
+        FitMode::Fit => format!("contain:{}", path),
+        FitMode::Tile => format!("tile:{}", path),
+        _ => String::from(path)
+    }
+}
+
+pub fn set_wallpaper_on(desktop: &Desktop, fit: &FitMode, path: &str) -> Result<(), io::Error> {
+    match desktop {
+        Desktop::Sway => {
+            // swaymsg joins its arguments into a single command, so the path has to be quoted
+            run("swaymsg", &["output", "*", "bg", &format!("\"{}\"", path), swaybg_mode(fit)])?;
+        },
+        Desktop::Swaybg => {
+            set_swaybg(&["-i", path, "-m", swaybg_mode(fit)])?;
+        },
+        Desktop::Swww => {
+            run("swww", &["img", "--resize", swww_resize(fit), path])?;
+        },
+        Desktop::Hyprpaper => {
+            run("hyprctl", &["hyprpaper", "preload", path])?;
+            run("hyprctl", &["hyprpaper", "wallpaper", &format!(",{}", hyprpaper_path(fit, path))])?;
+            run("hyprctl", &["hyprpaper", "unload", "unused"])?;
+        },
+        _ => unreachable!("{:?} is not a Wayland wallpaper setter", desktop)
+    }
+    Ok(())
+}
+
+pub fn set_wallpapers_on(desktop: &Desktop, fit: &FitMode, outputs: &[(String, String)]) -> Result<(), io::Error> {
+    match desktop {
+        Desktop::Sway => {
+            for (name, path) in outputs {
+                run("swaymsg", &["output", name, "bg", &format!("\"{}\"", path), swaybg_mode(fit)])?;
+            }
+        },
+        Desktop::Swaybg => {
+            // A single swaybg instance serves every output
+            let mut args = Vec::new();
+            for (name, path) in outputs {
+                args.extend(&["-o", name.as_str(), "-i", path.as_str(), "-m", swaybg_mode(fit)]);
+            }
+            set_swaybg(&args)?;
+        },
+        Desktop::Swww => {
+            for (name, path) in outputs {
+                run("swww", &["img", "-o", name, "--resize", swww_resize(fit), path])?;
+            }
+        },
+        Desktop::Hyprpaper => {
+            for (_, path) in outputs {
+                run("hyprctl", &["hyprpaper", "preload", path])?;
+            }
+            for (name, path) in outputs {
+                run("hyprctl", &["hyprpaper", "wallpaper", &format!("{},{}", name, hyprpaper_path(fit, path))])?;
+            }
+            run("hyprctl", &["hyprpaper", "unload", "unused"])?;
+        },
+        _ => unreachable!("{:?} is not a Wayland wallpaper setter", desktop)
+    }
+    Ok(())
+}
+
+fn swaylock_config() -> Result<PathBuf, io::Error> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME").ok_or_else(|| io::Error::new(ErrorKind::NotFound, "HOME is not set"))?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Ok(config_home.join("swaylock").join("config"))
+}
+
+// Points `image=` in swaylock's config at `path`, leaving the rest of the file alone
+pub fn set_swaylock_image(path: &str) -> Result<(), io::Error> {
+    let config_path = swaylock_config()?;
+    let config = match fs::read_to_string(&config_path) {
+        Ok(config) => config,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e)
+    };
+    let mut lines: Vec<String> = config.lines()
+        .filter(|line| !line.trim_start().starts_with("image="))
+        .map(String::from)
+        .collect();
+    lines.push(format!("image={}", path));
+    if let Some(dir) = config_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&config_path, lines.join("\n") + "\n")
+}
+
+fn set_swaybg(args: &[&str]) -> Result<(), io::Error> {
+    let mut swaybg = SWAYBG.lock().unwrap_or_else(|e| e.into_inner());
+    // Start the new instance before killing the old one so the background never goes blank
+    let child = Command::new("swaybg")
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut old) = swaybg.replace(child) {
+        if let Err(e) = old.kill() {
+            eprintln!("Could not stop previous swaybg: {}. Skipping...", e);
+        }
+        let _ = old.wait();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use super::super::linux::stubs::Stubs;
+
+    // swaybg is spawned in the background, so its log line may show up a little later
+    fn wait_for_calls(stubs: &Stubs, count: usize) -> Vec<String> {
+        for _ in 0..100 {
+            let calls = stubs.calls();
+            if calls.len() >= count {
+                return calls;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        stubs.calls()
+    }
+
+    #[test]
+    fn test_detect_compositor() {
+        let stubs = Stubs::new("wayland-detect");
+        stubs.set_var("WAYLAND_DISPLAY", "wayland-1");
+        stubs.set_var("SWAYSOCK", "/run/user/1000/sway-ipc.sock");
+        assert_eq!(detect_compositor(), Some(Desktop::Sway));
+        stubs.remove_var("SWAYSOCK");
+        stubs.set_var("HYPRLAND_INSTANCE_SIGNATURE", "abc");
+        assert_eq!(detect_compositor(), None);
+        stubs.add("swaybg", "");
+        assert_eq!(detect_compositor(), Some(Desktop::Swaybg));
+        stubs.add("hyprpaper", "");
+        assert_eq!(detect_compositor(), Some(Desktop::Hyprpaper));
+        stubs.remove_var("HYPRLAND_INSTANCE_SIGNATURE");
+        stubs.add("swww", "");
+        assert_eq!(detect_compositor(), Some(Desktop::Swww));
+        stubs.remove_var("WAYLAND_DISPLAY");
+        assert_eq!(detect_compositor(), None);
+    }
+
+    #[test]
+    fn test_sway_swww_and_hyprpaper() {
+        let stubs = Stubs::new("wayland-ipc");
+        stubs.add("swaymsg", "");
+        stubs.add("swww", "");
+        stubs.add("hyprctl", "");
+        set_wallpaper_on(&Desktop::Sway, &FitMode::Fill, "/tmp/a.jpg").unwrap();
+        set_wallpaper_on(&Desktop::Swww, &FitMode::Fill, "/tmp/a.jpg").unwrap();
+        set_wallpaper_on(&Desktop::Hyprpaper, &FitMode::Fit, "/tmp/a.jpg").unwrap();
+        assert_eq!(stubs.calls(), vec![
+            "swaymsg output * bg \"/tmp/a.jpg\" fill",
+            "swww img --resize crop /tmp/a.jpg",
+            "hyprctl hyprpaper preload /tmp/a.jpg",
+            "hyprctl hyprpaper wallpaper ,contain:/tmp/a.jpg",
+            "hyprctl hyprpaper unload unused"
+        ]);
+    }
+
+    #[test]
+    fn test_per_output() {
+        let stubs = Stubs::new("wayland-per-output");
+        stubs.add("swaymsg", "");
+        stubs.add("hyprctl", "");
+        let outputs = vec![
+            (String::from("DP-1"), String::from("/tmp/a.jpg")),
+            (String::from("HDMI-A-1"), String::from("/tmp/b.jpg"))
+        ];
+        set_wallpapers_on(&Desktop::Sway, &FitMode::Center, &outputs).unwrap();
+        set_wallpapers_on(&Desktop::Hyprpaper, &FitMode::Fill, &outputs).unwrap();
+        assert_eq!(stubs.calls(), vec![
+            "swaymsg output DP-1 bg \"/tmp/a.jpg\" center",
+            "swaymsg output HDMI-A-1 bg \"/tmp/b.jpg\" center",
+            "hyprctl hyprpaper preload /tmp/a.jpg",
+            "hyprctl hyprpaper preload /tmp/b.jpg",
+            "hyprctl hyprpaper wallpaper DP-1,/tmp/a.jpg",
+            "hyprctl hyprpaper wallpaper HDMI-A-1,/tmp/b.jpg",
+            "hyprctl hyprpaper unload unused"
+        ]);
+    }
+
+    #[test]
+    fn test_swaylock_image() {
+        let stubs = Stubs::new("wayland-swaylock");
+        stubs.set_var("XDG_CONFIG_HOME", &stubs.dir);
+        let config_path = stubs.dir.join("swaylock").join("config");
+        set_swaylock_image("/tmp/a.jpg").unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), "image=/tmp/a.jpg\n");
+        fs::write(&config_path, "color=000000\nimage=/tmp/a.jpg\nshow-failed-attempts\n").unwrap();
+        set_swaylock_image("/tmp/b.jpg").unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), "color=000000\nshow-failed-attempts\nimage=/tmp/b.jpg\n");
+    }
+
+    #[test]
+    fn test_swaybg_replaces_old_instance() {
+        let stubs = Stubs::new("wayland-swaybg");
+        stubs.add_script("swaybg", "exec /bin/sleep 60");
+        set_wallpaper_on(&Desktop::Swaybg, &FitMode::Fill, "/tmp/a.jpg").unwrap();
+        wait_for_calls(&stubs, 1);
+        let first = SWAYBG.lock().unwrap().as_ref().unwrap().id();
+        set_wallpaper_on(&Desktop::Swaybg, &FitMode::Stretch, "/tmp/b.jpg").unwrap();
+        let second = SWAYBG.lock().unwrap().as_ref().unwrap().id();
+        assert_ne!(first, second);
+        assert!(!Path::new(&format!("/proc/{}", first)).exists());
+        assert_eq!(wait_for_calls(&stubs, 2), vec!["swaybg -i /tmp/a.jpg -m fill", "swaybg -i /tmp/b.jpg -m stretch"]);
+
+        let mut last = SWAYBG.lock().unwrap().take().unwrap();
+        last.kill().unwrap();
+        last.wait().unwrap();
+    }
+}
@@ -1,6 +1,8 @@
-use std::{error::Error, fmt::Display, io::Read};
+use std::{env, error::Error, fmt::Display, io::Read};
 use serde::{Serialize, Deserialize};
 
+use crate::Config;
+
 const OPENWEATHER_URL: &str = "https://api.openweathermap.org/data/2.5/weather";
 
 #[derive(Serialize, Deserialize)]
@@ -24,10 +26,18 @@ impl Display for WeatherError {
 
 impl Error for WeatherError {}
 
-pub fn get_weather(key: &str, city: &str) -> Result<String, Box<dyn Error>> {
+// `openweather_api_url` or `AWC_OPENWEATHER_API_URL`, the full URL of the current weather endpoint
+pub fn openweather_url(config: &Config) -> String {
+    match &config.openweather_api_url {
+        Some(url) => url.clone(),
+        None => env::var("AWC_OPENWEATHER_API_URL").unwrap_or_else(|_| String::from(OPENWEATHER_URL))
+    }
+}
+
+pub fn get_weather(url: &str, key: &str, city: &str) -> Result<String, Box<dyn Error>> {
     let mut response = reqwest::blocking::get(format!(
         "{}?q={}&appid={}",
-        OPENWEATHER_URL,
+        url,
         &city,
         &key
     ))?;
@@ -42,7 +52,6 @@ pub fn get_weather(key: &str, city: &str) -> Result<String, Box<dyn Error>> {
 
 #[cfg(test)]
 mod tests {
-    use crate::Config;
     use super::*;
 
     #[test]
@@ -50,6 +59,7 @@ mod tests {
     fn test_weather() {
         let config = Config::from_path("config.json").unwrap();
         assert_eq!(get_weather(
+            &openweather_url(&config),
             &config.openweather_access_key.unwrap(),
             &config.city_weather).unwrap(), "Clear");
     }
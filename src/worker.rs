@@ -1,13 +1,17 @@
 
-use std::{sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}}, thread::{self, JoinHandle}};
+use std::{collections::HashMap, fs, io, path::Path, sync::{Arc, Mutex, mpsc::{self, Receiver, Sender}}, thread::{self, JoinHandle}};
 use std::{time::{Duration, SystemTime}};
 use chrono::{Local, Timelike};
-use rand::Rng;
+use rand::{Rng, seq::SliceRandom};
 
-use crate::{Config, DEFAULT_CONFIG_PATH, DEFAULT_DOWNLOAD_PATH, Hour, download_photo, get_weather, make_unsplash_client, search_photos, set_wallpaper};
+use crate::{Config, DEFAULT_CONFIG_PATH, DownloadQuality, Hour, ImageFormat, InvalidImage, Orientation, Output, Photo, PhotoInfo, PhotoSource, Query, RateLimit, RateLimitExceeded, Resolution, SourceKind, WallpaperInfo, WallpaperTarget, download_cached, get_weather, list_outputs, openweather_url, time_bucket, make_photo_sources, make_wallpaper_setter, format::check_image, source::{fit_to_display, request_format, weighted_order}};
 
 const MAXIMUM_ATTEMPTS: i32 = 5;
 const WAIT_SECS: u64 = 60;
+// Sources with fewer requests left than this are given a rest until the budget resets
+const LOW_RATE_LIMIT: u32 = 5;
+// How many more results of a source are tried after a download turns out corrupt
+const INVALID_RETRIES: usize = 2;
 
 #[derive(Debug)]
 pub enum Message {
@@ -26,19 +30,153 @@ pub enum State {
     Stopped
 }
 
+struct Target {
+    output: Option<String>,
+    lock_screen: bool,
+    query: Option<String>,
+    orientation: Option<Orientation>,
+    quality: DownloadQuality,
+    // What `FitToDisplay` sizes photos to
+    resolution: Option<Resolution>
+}
+
+// A random photo downloaded earlier, other than the ones in `used`. Corrupt ones are quarantined on the way
+fn cached_path(dir: &str, used: &[&String]) -> Option<String> {
+    let mut paths: Vec<String> = fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && ImageFormat::from_extension(path).is_some())
+        .map(|path| path.to_string_lossy().into_owned())
+        .filter(|path| !used.contains(&path))
+        .collect();
+    paths.shuffle(&mut rand::thread_rng());
+    paths.into_iter().find_map(|path| check_image(path).ok())
+}
+
+// The budget of a source that should be left alone until it resets
+fn resting(source: &dyn PhotoSource) -> Option<RateLimit> {
+    source.rate_limit().filter(|rate_limit| rate_limit.remaining < LOW_RATE_LIMIT)
+}
+
+// Without one wallpaper per monitor, the first monitor decides which way photos should face
+fn display_orientation(outputs: &Result<Vec<Output>, io::Error>) -> Option<Orientation> {
+    outputs.as_ref().ok()?.first().map(Output::orientation)
+}
+
+fn output_resolution(output: &Output) -> Resolution {
+    Resolution {
+        width: output.width,
+        height: output.height,
+        dpr: output.scale
+    }
+}
+
+// `resolution` in the config wins over whatever the first monitor says
+fn display_resolution(config: &Config, outputs: &Result<Vec<Output>, io::Error>) -> Option<Resolution> {
+    config.resolution.clone().or_else(|| outputs.as_ref().ok()?.first().map(output_resolution))
+}
+
+// Whether any source picks photos to suit the way the display faces
+fn follows_orientation(config: &Config) -> bool {
+    config.sources.iter().any(|source| match &source.kind {
+        SourceKind::Unsplash { .. } => config.unsplash_filters.orientation.is_none(),
+        SourceKind::Pexels | SourceKind::Bing { .. } | SourceKind::Reddit { .. } => true,
+        SourceKind::Local { .. } | SourceKind::Wikimedia | SourceKind::Apod { .. } | SourceKind::Feed { .. } | SourceKind::Json { .. } => false
+    })
+}
+
+// Listing monitors runs a program, so it's only done when something depends on them
+fn needs_outputs(config: &Config) -> bool {
+    let fits_display = matches!(config.quality, DownloadQuality::FitToDisplay) && config.resolution.is_none();
+    config.per_output || fits_display || follows_orientation(config)
+}
+
+fn targets(config: &Config) -> Vec<Target> {
+    let outputs = if needs_outputs(config) {
+        list_outputs()
+    } else {
+        Ok(Vec::new())
+    };
+    let mut targets = match config.target {
+        WallpaperTarget::LockScreen => Vec::new(),
+        WallpaperTarget::Desktop | WallpaperTarget::Both => desktop_targets(config, &outputs)
+    };
+    // Otherwise the lock screen shares a photo with the desktop
+    if config.target == WallpaperTarget::LockScreen || (config.target == WallpaperTarget::Both && config.distinct_lock_screen) {
+        targets.push(Target {
+            output: None,
+            lock_screen: true,
+            query: None,
+            orientation: display_orientation(&outputs),
+            quality: config.quality.clone(),
+            resolution: display_resolution(config, &outputs)
+        });
+    }
+    targets
+}
+
+fn desktop_targets(config: &Config, outputs: &Result<Vec<Output>, io::Error>) -> Vec<Target> {
+    let single = vec![Target {
+        output: None,
+        lock_screen: false,
+        query: None,
+        orientation: display_orientation(outputs),
+        quality: config.quality.clone(),
+        resolution: display_resolution(config, outputs)
+    }];
+    if !config.per_output {
+        return single;
+    }
+    let outputs = match outputs {
+        Ok(outputs) if !outputs.is_empty() => outputs,
+        Ok(_) => return single,
+        Err(e) => {
+            eprintln!("Failed to list outputs: {}. Using a single wallpaper...", e);
+            return single;
+        }
+    };
+    outputs.iter().map(|output| {
+        let output_config = config.outputs.iter().find(|output_config| output_config.name == output.name);
+        Target {
+            query: output_config.and_then(|output_config| output_config.query.clone()),
+            orientation: Some(output_config.and_then(|output_config| output_config.orientation.clone())
+                .unwrap_or_else(|| output.orientation())),
+            quality: output_config.and_then(|output_config| output_config.quality.clone())
+                .unwrap_or_else(|| config.quality.clone()),
+            resolution: Some(config.resolution.clone().unwrap_or_else(|| output_resolution(output))),
+            output: Some(output.name.clone()),
+            lock_screen: false
+        }
+    }).collect()
+}
+
 pub struct Worker {
     thread: Option<JoinHandle<()>>,
     sender: Sender<Message>,
     meta_sender: Sender<MetaMessage>,
-    pub state: Arc<Mutex<State>>
+    pub state: Arc<Mutex<State>>,
+    // The wallpaper currently set, with the credits some sources ask to be shown
+    pub info: Arc<Mutex<Option<WallpaperInfo>>>,
+    // What's left of the hourly budget of sources that have one, i.e. Unsplash
+    pub rate_limit: Arc<Mutex<Option<RateLimit>>>
 }
 
 impl Worker {
     pub fn new() -> Worker {
+        Worker::with_config_path(DEFAULT_CONFIG_PATH)
+    }
+
+    // The config is read again every time the worker starts
+    pub fn with_config_path(config_path: &str) -> Worker {
+        let config_path = String::from(config_path);
         let (tx, rx) = mpsc::channel();
         let (sender, receiver) = mpsc::channel();
         let state = Arc::new(Mutex::new(State::Stopped));
         let state_thread = state.clone();
+        let info = Arc::new(Mutex::new(None));
+        let info_thread = info.clone();
+        let rate_limit = Arc::new(Mutex::new(None));
+        let rate_limit_thread = rate_limit.clone();
         let thread = thread::spawn(move || {
             loop {
                 let mut state = state_thread.lock().unwrap();
@@ -48,7 +186,7 @@ impl Worker {
                     MetaMessage::Start => {},
                     MetaMessage::Quit => break
                 }
-                Worker::work(&receiver, state_thread.clone());
+                Worker::work(&config_path, &receiver, state_thread.clone(), info_thread.clone(), rate_limit_thread.clone());
             }
             
         });
@@ -57,24 +195,25 @@ impl Worker {
             thread: Some(thread),
             sender,
             meta_sender: tx,
-            state
+            state,
+            info,
+            rate_limit
         };
 
         worker
     }
 
-    fn work(receiver: &Receiver<Message>, state: Arc<Mutex<State>>) {
-        let config = match Config::from_path(DEFAULT_CONFIG_PATH) {
+    fn work(config_path: &str, receiver: &Receiver<Message>, state: Arc<Mutex<State>>, current: Arc<Mutex<Option<WallpaperInfo>>>, rate_limit: Arc<Mutex<Option<RateLimit>>>) {
+        let config = match Config::from_path(config_path) {
             Ok(config) => config,
             Err(e) => {
                 eprintln!("Failed to read config: {}", e);
                 return;
             }
         };
-        let client = match make_unsplash_client(&config) {
-            Ok(client) => client,
-            Err(_) => {
-                eprintln!("\
+        let sources = make_photo_sources(&config);
+        if sources.is_empty() {
+            eprintln!("\
 Looks like this is the first time you use AWC.
 You need to do a few things first - otherwise AWC won't run.
 A `config.json` has been generated under the cwd, please modify
@@ -99,15 +238,22 @@ Raw, Full, Regular, Small and Thumb.
 Run the program again after you've updated the configs accordingly.
 
 Have a lot of fun...");
+            return;
+        }
+        let weights: Vec<u32> = sources.iter().map(|source| source.weight).collect();
+        let setter = match make_wallpaper_setter(&config) {
+            Ok(setter) => setter,
+            Err(e) => {
+                eprintln!("Failed to prepare wallpaper setter: {}", e);
                 return;
             }
         };
         let mut attempts = 0;
         let mut last_instant: Option<SystemTime> = None;
-        let mut last_path: Option<String> = None;
+        let mut last_paths: Vec<String> = Vec::new();
     
         if config.disable_cache {
-            match std::fs::remove_dir_all(DEFAULT_DOWNLOAD_PATH) {
+            match std::fs::remove_dir_all(&config.download_dir) {
                 Ok(_) => {
                     println!("Image cache directory has been cleaned up");
                 }
@@ -144,80 +290,237 @@ Have a lot of fun...");
             *state_mut = State::Running;
             drop(state_mut);
     
-            let mut query = Hour(now.hour()).to_string();
-            match &config.openweather_access_key {
-                Some(x) => match get_weather(x, &config.city_weather) {
+            let (bucket, mut query) = match time_bucket(&config.time_buckets, Hour::of(&now)) {
+                Some(bucket) => match bucket.pick_term(&mut rand::thread_rng()) {
+                    Some(term) => (bucket.name.clone(), term),
+                    None => {
+                        eprintln!("The time bucket covering {}:00 has no name or terms, searching without one...", now.hour());
+                        (bucket.name.clone(), String::new())
+                    }
+                },
+                None => {
+                    eprintln!("No time bucket covers {}:00, searching without one...", now.hour());
+                    (String::new(), String::new())
+                }
+            };
+            let weather = match &config.openweather_access_key {
+                Some(x) => match get_weather(&openweather_url(&config), x, &config.city_weather) {
                     Ok(x) => {
-                        query.push(' ');
+                        if !query.is_empty() {
+                            query.push(' ');
+                        }
                         query.push_str(&x);
+                        Some(x)
                     },
                     Err(e) => {
                         eprintln!("Failed to get weather information: {} Skipping...", e);
+                        None
                     }
                 },
-                None => {}
+                None => None
             };
             
-            println!("Trying to search from unsplash with: {}", query);
-            
-            let results = match search_photos(&client, &query) {
-                Ok(results) => {
-                    results
-                },
-                Err(e) => {
-                    attempts += 1;
-                    if attempts >= MAXIMUM_ATTEMPTS {
-                        eprintln!("Failed to get photo list: {}. Too many retries. Stopping...", e);
+            // Every target gets a photo of its own, without per-output wallpapers there's just one
+            let targets = targets(&config);
+            let mut paths = HashMap::new();
+            let mut lock_path = None;
+            let mut photos = Vec::new();
+            let mut taken: Vec<(String, String)> = Vec::new();
+            let mut failure = None;
+            for target in &targets {
+                let mut query = query.clone();
+                if let Some(extra) = &target.query {
+                    if !query.is_empty() {
+                        query.push(' ');
+                    }
+                    query.push_str(extra);
+                }
+
+                // Sources are tried in a random order by weight until one of them comes up with a photo
+                let mut found = None;
+                let mut errors = Vec::new();
+                let mut throttled = false;
+                for index in weighted_order(&weights, &mut rand::thread_rng()) {
+                    let source = &*sources[index].source;
+                    if let Some(rate_limit) = resting(source) {
+                        errors.push(format!("{}: only {} of {} requests left this hour", source.name(), rate_limit.remaining, rate_limit.limit));
+                        throttled = true;
+                        continue;
+                    }
+                    println!("Trying to search from {} with: {}", source.name(), query);
+                    let results = match source.search(&Query {
+                        text: query.clone(),
+                        bucket: bucket.clone(),
+                        orientation: target.orientation.clone()
+                    }) {
+                        Ok(results) => results,
+                        Err(e) => {
+                            throttled |= e.is::<RateLimitExceeded>();
+                            errors.push(format!("{}: {}", source.name(), e));
+                            continue;
+                        }
+                    };
+                    // Prefer photos that aren't on another monitor already
+                    let mut candidates: Vec<&Photo> = results.iter()
+                        .filter(|result| !taken.contains(&(result.source.clone(), result.id.clone())))
+                        .collect();
+                    if candidates.is_empty() {
+                        candidates = results.iter().collect();
+                    }
+                    if candidates.is_empty() {
+                        errors.push(format!("{}: no photos found", source.name()));
+                        continue;
+                    }
+                    // A corrupt download is quarantined and another result tried in its place
+                    for _ in 0..=INVALID_RETRIES {
+                        if candidates.is_empty() {
+                            break;
+                        }
+                        let choice = candidates.remove(rand::thread_rng().gen_range(0..candidates.len())).clone();
+
+                        let (sized, quality) = fit_to_display(&choice, &target.quality, target.resolution.as_ref());
+                        let (sized, quality) = match config.image_format.filter(|format| setter.formats().contains(format)) {
+                            Some(format) => request_format(&sized, &quality, format),
+                            None => (sized, quality)
+                        };
+                        match download_cached(source, &sized, &quality, setter.formats(), &config.download_dir) {
+                            Ok(path) => {
+                                found = Some(PhotoInfo {
+                                    id: choice.id,
+                                    source: choice.source,
+                                    attribution: choice.attribution,
+                                    path,
+                                    query: query.clone(),
+                                    output: target.output.clone(),
+                                    lock_screen: target.lock_screen
+                                });
+                                break;
+                            },
+                            Err(e) => {
+                                throttled |= e.is::<RateLimitExceeded>();
+                                errors.push(format!("{}: download failed: {}", source.name(), e));
+                                if !e.is::<InvalidImage>() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    if found.is_some() {
                         break;
-                    } else {
-                        eprintln!("Failed to get photo list: {}, Trying again in {} seconds...", e, WAIT_SECS);
-                        thread::sleep(Duration::from_secs(WAIT_SECS));
                     }
-                    continue;
                 }
-            };
-            let choice = rand::thread_rng().gen_range(0..results.results.len());
-            let choice = &results.results[choice];
-            
-            let path = match download_photo(&client, choice, config.quality.clone()) {
-                Ok(path) => path,
-                Err(e) => {
-                    attempts += 1;
-                    if attempts >= MAXIMUM_ATTEMPTS {
-                        eprintln!("Download failed: {}. Too many retries. Stopping...", e);
+                // Rather than spending what's left of the budget on retries, an earlier photo is used again
+                if found.is_none() && throttled {
+                    let used: Vec<&String> = paths.values().chain(lock_path.iter()).collect();
+                    if let Some(path) = cached_path(&config.download_dir, &used) {
+                        println!("Running low on requests, using {} from the cache...", path);
+                        found = Some(PhotoInfo {
+                            id: Path::new(&path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+                            source: String::from("cache"),
+                            attribution: None,
+                            path,
+                            query: query.clone(),
+                            output: target.output.clone(),
+                            lock_screen: target.lock_screen
+                        });
+                    }
+                }
+                let info = match found {
+                    Some(info) => info,
+                    None => {
+                        failure = Some(format!("Failed to get a photo for {} ({})", query, errors.join(", ")));
                         break;
-                    } else {
-                        eprintln!("Download failed: {}. Trying again in {} seconds...", e, WAIT_SECS);
-                        thread::sleep(Duration::from_secs(WAIT_SECS));
                     }
-                    continue;
+                };
+                let path = info.path.clone();
+                taken.push((info.source.clone(), info.id.clone()));
+                photos.push(info);
+                if target.lock_screen {
+                    lock_path = Some(path);
+                } else {
+                    paths.insert(target.output.clone().unwrap_or_default(), path);
                 }
-            };
+            }
+            *rate_limit.lock().unwrap() = sources.iter().find_map(|source| source.source.rate_limit());
+            if let Some(failure) = failure {
+                attempts += 1;
+                if attempts >= MAXIMUM_ATTEMPTS {
+                    eprintln!("{}. Too many retries. Stopping...", failure);
+                    break;
+                } else {
+                    eprintln!("{}. Trying again in {} seconds...", failure, WAIT_SECS);
+                    thread::sleep(Duration::from_secs(WAIT_SECS));
+                }
+                continue;
+            }
     
             attempts = 0;
             last_instant = Some(this_instant);
-            if config.disable_cache {
-                if let Some(last_path) = last_path {
-                    if path != last_path {
-                        match std::fs::remove_file(last_path) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                eprintln!("Could not removed cached image for some reason: {}. Skipping...", e);
-                            }
-                        }
-                    }
+            for (output, path) in &paths {
+                if output.is_empty() {
+                    println!("New photo downloaded at: {}. Setting wallpaper...", path);
+                } else {
+                    println!("New photo for {} downloaded at: {}. Setting wallpaper...", output, path);
                 }
             }
-            last_path = Some(path.clone());
-            println!("New photo downloaded at: {}. Setting wallpaper...", path);
-            
-            match set_wallpaper(&path) {
+
+            let result = if targets.iter().any(|target| target.output.is_some()) {
+                setter.set_wallpapers(&paths)
+            } else if let Some(path) = paths.get("") {
+                setter.set_wallpaper(path)
+            } else {
+                Ok(())
+            };
+            match result {
                 Ok(_) => {}
                 Err(e) => {
                     eprintln!("Failed to set wallpaper: {}. Stopping...", e);
                     return;
                 }
             }
+
+            if config.target != WallpaperTarget::Desktop {
+                if let Some(path) = lock_path.as_ref().or_else(|| paths.values().next()) {
+                    println!("Setting lock screen to: {}...", path);
+                    if let Err(e) = setter.set_lock_screen(path) {
+                        eprintln!("Failed to set lock screen: {}. Skipping...", e);
+                    }
+                }
+            }
+
+            let info = WallpaperInfo {
+                time: now.to_rfc3339(),
+                weather,
+                photos
+            };
+            for attribution in info.photos.iter().filter_map(|photo| photo.attribution.as_ref()) {
+                println!("{}", attribution);
+            }
+            let new_paths: Vec<String> = paths.values().chain(lock_path.iter()).cloned().collect();
+            match setter.set_info(&info) {
+                Ok(_) => {
+                    // Only now that nothing points at the previous photos can they go
+                    if config.disable_cache {
+                        for last_path in &last_paths {
+                            if !new_paths.contains(last_path) {
+                                match std::fs::remove_file(last_path) {
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        eprintln!("Could not removed cached image for some reason: {}. Skipping...", e);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    last_paths = new_paths;
+                },
+                Err(e) => {
+                    // The export may still point at the previous photos, so they're kept until the next one succeeds
+                    eprintln!("Failed to publish wallpaper information: {}. Skipping...", e);
+                    last_paths.extend(new_paths);
+                }
+            }
+            *current.lock().unwrap() = Some(info);
     
             thread::sleep(Duration::from_secs(config.repeat_secs));
         }
@@ -240,3 +543,52 @@ impl Drop for Worker {
         self.thread.take().unwrap().join().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, time::SystemTime};
+
+    use crate::{UnsplashSource, record_rate_limit};
+    use super::*;
+
+    #[test]
+    fn test_resting() {
+        let path = env::temp_dir().join(format!("awc-resting-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut config = Config::from_path(path.to_str().unwrap()).unwrap();
+        config.unsplash_access_key = Some(String::from("resting-key"));
+        let source = UnsplashSource::new(&config, Vec::new()).unwrap();
+        let low = RateLimit { limit: 50, remaining: 2 };
+
+        record_rate_limit(low, SystemTime::now());
+        assert_eq!(resting(&source), Some(low));
+        // Once the hour is up, Unsplash is asked again
+        record_rate_limit(low, SystemTime::now() - Duration::from_secs(3601));
+        assert_eq!(resting(&source), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_needs_outputs() {
+        let path = env::temp_dir().join(format!("awc-needs-outputs-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut config = Config::from_path(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        // Unsplash follows the display unless an orientation is configured
+        assert!(needs_outputs(&config));
+        config.unsplash_filters.orientation = Some(Orientation::Landscape);
+        assert!(!needs_outputs(&config));
+
+        config.sources = serde_json::from_str(r#"[{"type": "Local", "dirs": ["photos"]}, {"type": "Wikimedia"}]"#).unwrap();
+        assert!(!needs_outputs(&config));
+        config.quality = DownloadQuality::FitToDisplay;
+        assert!(needs_outputs(&config));
+        config.resolution = Some(Resolution { width: 2560, height: 1440, dpr: 1.0 });
+        assert!(!needs_outputs(&config));
+        config.per_output = true;
+        assert!(needs_outputs(&config));
+        config.per_output = false;
+        config.sources = serde_json::from_str(r#"[{"type": "Reddit", "subreddit": "EarthPorn"}]"#).unwrap();
+        assert!(needs_outputs(&config));
+    }
+}
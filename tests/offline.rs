@@ -0,0 +1,185 @@
+// Runs AWC against a local stub of the Unsplash and OpenWeather APIs, so no keys or network are needed
+
+#[path = "../src/source/stub.rs"]
+mod stub;
+
+use std::{env, fs, path::{Path, PathBuf}, process, thread, time::{Duration, Instant}};
+
+use awc::{Config, DownloadQuality, ImageFormat, InvalidImage, MetaMessage, PhotoSource, Query, SearchResults, UnsplashFilters, UnsplashSource, WallpaperInfo, Worker, download_cached, get_weather, make_unsplash_client, search_photos, unsplash_api_url};
+use stub::StubServer;
+
+const SEARCH: &str = include_str!("fixtures/unsplash_search.json");
+const WEATHER: &str = include_str!("fixtures/openweather_dublin.json");
+const IMAGE: &[u8] = include_bytes!("../ferris.png");
+
+fn stub() -> StubServer {
+    let server = StubServer::start();
+    server.route("/search/photos", "application/json", SEARCH.as_bytes());
+    server.route("/data/2.5/weather", "application/json", WEATHER.as_bytes());
+    server.route("/images/offlineNoon1.png", "image/png", IMAGE);
+    server.route("/images/offlineNoon2.png", "image/png", IMAGE);
+    server.route("/photos/offlineNoon1/download", "application/json", b"{\"url\": \"{base}/images/offlineNoon1.png\"}");
+    server.route("/photos/offlineNoon2/download", "application/json", b"{\"url\": \"{base}/images/offlineNoon2.png\"}");
+    server
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("awc-offline-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(dir: &Path, server: &StubServer) -> Config {
+    let mut config = Config::from_path(dir.join("config.json").to_str().unwrap()).unwrap();
+    config.unsplash_access_key = Some(String::from("offline-key"));
+    config.unsplash_api_url = Some(server.url.clone());
+    config.openweather_access_key = Some(String::from("offline-weather-key"));
+    config.openweather_api_url = Some(format!("{}/data/2.5/weather", server.url));
+    config.download_dir = dir.join("download").to_string_lossy().into_owned();
+    config
+}
+
+#[test]
+fn test_api_url() {
+    let dir = temp_dir("api-url");
+    let server = StubServer::start();
+    let mut config = config(&dir, &server);
+    assert_eq!(unsplash_api_url(&config), server.url);
+    config.unsplash_api_url = Some(format!("{}/", server.url));
+    assert_eq!(unsplash_api_url(&config), server.url);
+}
+
+#[test]
+fn test_search() {
+    let dir = temp_dir("search");
+    let server = stub();
+    let config = config(&dir, &server);
+
+    let client = make_unsplash_client(&config).unwrap();
+    let results: SearchResults = search_photos(&client, &server.url, "noon", &UnsplashFilters::default(), 1).unwrap();
+    assert_eq!(results.total, 2);
+    assert_eq!(results.results[1].alt_description.as_deref(), Some("a lighthouse in the rain"));
+    assert_eq!(server.requests(), vec!["/search/photos?query=noon&per_page=100&page=1"]);
+    assert!(server.headers()[0].contains(&String::from("authorization: client-id offline-key")));
+
+    let source = UnsplashSource::new(&config, Vec::new()).unwrap();
+    let photos = source.search(&Query::new("noon Rain")).unwrap();
+    let ids: Vec<&str> = photos.iter().map(|photo| photo.id.as_str()).collect();
+    assert_eq!(ids, vec!["offlineNoon1", "offlineNoon2"]);
+    assert_eq!(photos[0].attribution.as_deref(), Some("Photo by Amy Witha on Unsplash"));
+    assert_eq!(photos[1].attribution.as_deref(), Some("Photo by ljones on Unsplash"));
+}
+
+#[test]
+fn test_download() {
+    let dir = temp_dir("download");
+    let server = stub();
+    let config = config(&dir, &server);
+    let source = UnsplashSource::new(&config, Vec::new()).unwrap();
+    let mut photos = source.search(&Query::new("noon")).unwrap();
+
+    // The stub serves a PNG, which is saved as one whatever the URL says
+    let formats = [ImageFormat::Jpeg, ImageFormat::Png];
+    let path = download_cached(&source, &photos[0], &DownloadQuality::Regular, &formats, &config.download_dir).unwrap();
+    assert_eq!(path, format!("{}/unsplash-offlineNoon1.png", config.download_dir));
+    assert_eq!(fs::read(&path).unwrap(), IMAGE);
+    let requests = server.requests();
+    assert!(requests.contains(&String::from("/photos/offlineNoon1/download?ixid=1")));
+    assert!(requests.contains(&String::from("/images/offlineNoon1.png?ixid=1&w=1080")));
+
+    // Photos already in the cache are neither downloaded nor reported again
+    let count = server.requests().len();
+    assert_eq!(download_cached(&source, &photos[0], &DownloadQuality::Regular, &formats, &config.download_dir).unwrap(), path);
+    assert_eq!(server.requests().len(), count);
+
+    // Photos downloaded through a source without the cache are reported to Unsplash as well
+    let photo = photos.remove(1);
+    let path = dir.join("offlineNoon2.jpg");
+    source.download(&photo, &DownloadQuality::Thumb, path.to_str().unwrap()).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), IMAGE);
+    assert!(server.requests().contains(&String::from("/photos/offlineNoon2/download?ixid=2")));
+}
+
+#[test]
+fn test_weather() {
+    let server = stub();
+    let weather = get_weather(&format!("{}/data/2.5/weather", server.url), "offline-weather-key", "Dublin").unwrap();
+    assert_eq!(weather, "Rain");
+    assert_eq!(server.requests(), vec!["/data/2.5/weather?q=Dublin&appid=offline-weather-key"]);
+    assert!(get_weather(&format!("{}/nowhere", server.url), "offline-weather-key", "Dublin").is_err());
+}
+
+fn worker_config(dir: &Path, server: &StubServer) -> PathBuf {
+    let mut config = config(dir, server);
+    let export_dir = dir.join("export");
+    config.export_dir = Some(export_dir.to_string_lossy().into_owned());
+    config.export_only = true;
+    config.disable_cache = false;
+    config.per_output = false;
+    let config_path = dir.join("config.json");
+    fs::write(&config_path, serde_json::to_string(&config).unwrap()).unwrap();
+    config_path
+}
+
+fn run_worker(config_path: &Path) -> WallpaperInfo {
+    let worker = Worker::with_config_path(config_path.to_str().unwrap());
+    worker.meta_send(MetaMessage::Start);
+    let started = Instant::now();
+    loop {
+        if let Some(info) = worker.info.lock().unwrap().clone() {
+            return info;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "The worker never set a wallpaper");
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_worker_cycle() {
+    let dir = temp_dir("worker");
+    let server = stub();
+    let config_path = worker_config(&dir, &server);
+    let export_dir = dir.join("export");
+
+    let info = run_worker(&config_path);
+
+    assert_eq!(info.weather.as_deref(), Some("Rain"));
+    assert_eq!(info.photos.len(), 1);
+    let photo = &info.photos[0];
+    assert_eq!(photo.source, "unsplash");
+    assert!(photo.query.ends_with(" Rain"));
+    assert!(photo.attribution.as_deref().unwrap_or("").ends_with(" on Unsplash"));
+    assert_eq!(fs::read(&photo.path).unwrap(), IMAGE);
+    assert!(photo.path.starts_with(dir.join("download").to_str().unwrap()));
+    assert!(photo.path.ends_with(".png"));
+    assert!(server.requests().iter().any(|request| request.starts_with(&format!("/photos/{}/download", photo.id))));
+
+    let exported: WallpaperInfo = serde_json::from_str(&fs::read_to_string(export_dir.join("current.json")).unwrap()).unwrap();
+    assert_eq!(exported.photos[0].id, photo.id);
+}
+
+#[test]
+fn test_corrupt_download() {
+    let dir = temp_dir("corrupt");
+    let server = StubServer::start();
+    server.route("/search/photos", "application/json", SEARCH.replace("offlineNoon", "offlineBroken").as_bytes());
+    server.route("/data/2.5/weather", "application/json", WEATHER.as_bytes());
+    // An error page where the first photo should be, and the second one intact
+    server.route("/images/offlineBroken1.png", "image/jpeg", "<html><body>Bad Gateway</body></html>".repeat(50).as_bytes());
+    server.route("/images/offlineBroken2.png", "image/png", IMAGE);
+
+    let config = config(&dir, &server);
+    let source = UnsplashSource::new(&config, Vec::new()).unwrap();
+    let broken = source.search(&Query::new("noon")).unwrap().remove(0);
+    let e = download_cached(&source, &broken, &DownloadQuality::Regular, &[ImageFormat::Jpeg, ImageFormat::Png], &config.download_dir).unwrap_err();
+    assert!(e.is::<InvalidImage>(), "{}", e);
+    let download_dir = dir.join("download");
+    assert!(!download_dir.join("unsplash-offlineBroken1.jpg").exists());
+    assert!(download_dir.join("quarantine/unsplash-offlineBroken1.jpg").exists());
+
+    // The worker moves on to the other result rather than setting the broken one
+    let info = run_worker(&worker_config(&dir, &server));
+    assert_eq!(info.photos[0].id, "offlineBroken2");
+    assert_eq!(fs::read(&info.photos[0].path).unwrap(), IMAGE);
+}